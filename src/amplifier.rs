@@ -0,0 +1,175 @@
+use super::computer::{Computer, ErrorKind, Event, InputQueue, IntcodeError};
+use rayon::prelude::*;
+
+/// Machines connected so that each one's outputs are the next one's inputs.
+pub struct Pipeline {
+    pub machines: Vec<Computer<InputQueue>>,
+}
+
+impl Pipeline {
+    /// Loads a copy of the program per phase setting, each machine receiving
+    /// its phase as first input.
+    pub fn new(program: &[i64], phases: &[i64]) -> Self {
+        let machines = phases
+            .iter()
+            .map(|&phase| {
+                let mut computer = Computer::new(program.to_vec(), InputQueue::new());
+                computer.push_input(phase);
+                computer
+            })
+            .collect();
+        Pipeline { machines }
+    }
+
+    /// Feeds `input` to the first machine and runs the chain until the last
+    /// machine halts. Returns its last output, or 0 if there was none.
+    pub fn run_chain(&mut self, input: i64) -> Result<i64, IntcodeError> {
+        self.run(input, false)
+    }
+
+    /// Like `run_chain`, but the last machine's outputs are also fed back to
+    /// the first one.
+    pub fn run_feedback_loop(&mut self, input: i64) -> Result<i64, IntcodeError> {
+        self.run(input, true)
+    }
+
+    fn run(&mut self, input: i64, feedback: bool) -> Result<i64, IntcodeError> {
+        let count = self.machines.len();
+        if count == 0 {
+            return Ok(input);
+        }
+        let mut last_output = 0;
+        self.machines[0].push_input(input);
+        loop {
+            let mut halted = vec![false; count];
+            let mut progress = false;
+            for (i, machine_halted) in halted.iter_mut().enumerate() {
+                loop {
+                    let event = self.machines[i].run_until_event()?;
+                    match event {
+                        Event::Output(value) => {
+                            progress = true;
+                            if i + 1 < count {
+                                self.machines[i + 1].push_input(value);
+                            } else {
+                                last_output = value;
+                                if feedback {
+                                    self.machines[0].push_input(value);
+                                }
+                            }
+                        }
+                        Event::NeedsInput => break,
+                        Event::Halted => {
+                            *machine_halted = true;
+                            break;
+                        }
+                    }
+                }
+            }
+            if halted[count - 1] {
+                return Ok(last_output);
+            }
+            if !progress {
+                // Every machine is halted or waiting for an input that will never come
+                let stuck = halted.iter().position(|&halted| !halted).unwrap();
+                return Err(self.machines[stuck].error(ErrorKind::OutOfInputs));
+            }
+        }
+    }
+}
+
+/// All the orderings of `values`.
+pub fn permutations(values: &[i64]) -> Vec<Vec<i64>> {
+    if values.len() <= 1 {
+        return vec![values.to_vec()];
+    }
+    let mut result = Vec::new();
+    for i in 0..values.len() {
+        let mut rest = values.to_vec();
+        let first = rest.remove(i);
+        for mut permutation in permutations(&rest) {
+            permutation.insert(0, first);
+            result.push(permutation);
+        }
+    }
+    result
+}
+
+/// Tries every ordering of `phases` in parallel, and returns the one giving
+/// the highest signal for an input of 0, along with that signal.
+///
+/// Orderings for which the program fails are skipped.
+pub fn best_phases(program: &[i64], phases: &[i64], feedback: bool) -> Option<(Vec<i64>, i64)> {
+    permutations(phases)
+        .into_par_iter()
+        .filter_map(|phases| {
+            let mut pipeline = Pipeline::new(program, &phases);
+            let signal = if feedback {
+                pipeline.run_feedback_loop(0)
+            } else {
+                pipeline.run_chain(0)
+            };
+            signal.ok().map(|signal| (phases, signal))
+        })
+        .max_by_key(|&(_, signal)| signal)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const CHAIN1: &[i64] = &[
+        3, 15, 3, 16, 1002, 16, 10, 16, 1, 16, 15, 15, 4, 15, 99, 0, 0,
+    ];
+    /// Day 7 example: the best chain signal is 54321, for phases 0 to 4
+    pub(crate) const CHAIN2: &[i64] = &[
+        3, 23, 3, 24, 1002, 24, 10, 24, 1002, 23, -1, 23, 101, 5, 23, 23, 1, 24, 23, 23, 4, 23, 99,
+        0, 0,
+    ];
+    /// Day 7 example: the best feedback loop signal is 139629729, for
+    /// phases 9 to 5
+    pub(crate) const FEEDBACK: &[i64] = &[
+        3, 26, 1001, 26, -4, 26, 3, 27, 1002, 27, 2, 27, 1, 27, 26, 27, 4, 27, 1001, 28, -1, 28,
+        1005, 28, 6, 99, 0, 0, 5,
+    ];
+
+    #[test]
+    fn chain() {
+        let mut pipeline = Pipeline::new(CHAIN1, &[4, 3, 2, 1, 0]);
+        assert_eq!(pipeline.run_chain(0), Ok(43210));
+        assert_eq!(
+            best_phases(CHAIN2, &[0, 1, 2, 3, 4], false),
+            Some((vec![0, 1, 2, 3, 4], 54321))
+        );
+    }
+
+    #[test]
+    fn feedback_loop() {
+        let mut pipeline = Pipeline::new(FEEDBACK, &[9, 8, 7, 6, 5]);
+        assert_eq!(pipeline.run_feedback_loop(0), Ok(139629729));
+        assert_eq!(
+            best_phases(FEEDBACK, &[5, 6, 7, 8, 9], true),
+            Some((vec![9, 8, 7, 6, 5], 139629729))
+        );
+    }
+
+    #[test]
+    fn deadlock() {
+        // The second machine reads twice but only ever gets one value
+        let mut pipeline = Pipeline {
+            machines: vec![
+                Computer::new(vec![3, 9, 4, 9, 99], InputQueue::new()),
+                Computer::new(vec![3, 9, 3, 9, 99], InputQueue::new()),
+            ],
+        };
+        let error = pipeline.run_chain(1).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::OutOfInputs);
+        assert_eq!(error.ip, 2);
+    }
+
+    #[test]
+    fn permutations_count() {
+        assert_eq!(permutations(&[1, 2, 3, 4, 5]).len(), 120);
+        assert_eq!(permutations(&[1, 2]), vec![vec![1, 2], vec![2, 1]]);
+    }
+}
@@ -0,0 +1,643 @@
+//! Assembler for a small Intcode assembly language, using the same syntax as
+//! the disassembler listings:
+//!
+//! ```text
+//! ; Comments start with a semicolon
+//! macro INC cell               ; macros take comma separated parameters
+//!     ADD cell, #1, cell
+//! endm
+//!
+//! start:  IN [counter]         ; labels end with a colon
+//! loop\@: ...                  ; in a macro body, \@ is unique per expansion
+//!         OUT rb-1             ; relative operands
+//!         INC [counter]
+//!         JNZ [counter], #loop ; labels can be used in any operand
+//!         HLT
+//! counter: DATA 0, end+1       ; raw words, labels allowed
+//! 12:     DATA 1               ; a numeric label checks the current address
+//! ```
+//!
+//! Operands are written `[address]` in position mode, `#value` in immediate
+//! mode and `rb+offset` in relative mode.
+
+use super::computer::{Mode, OpCode};
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+const OPCODES: [i64; 10] = [1, 2, 3, 4, 5, 6, 7, 8, 9, 99];
+const MAX_EXPANSION_DEPTH: usize = 32;
+
+/// Assembly error, located in the source text (both 1-based).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: {}",
+            self.line, self.column, self.message
+        )
+    }
+}
+
+impl Error for AsmError {}
+
+/// A source line, comments stripped.
+#[derive(Clone)]
+struct Line {
+    text: String,
+    number: usize,
+    /// Column of the outermost macro invocation and the name of the macro,
+    /// for lines produced by a macro expansion
+    expansion: Option<(usize, String)>,
+}
+
+impl Line {
+    /// Builds an error at `offset` bytes into the line. Errors in expanded
+    /// lines are reported at the macro invocation.
+    fn error<S: Into<String>>(&self, offset: usize, message: S) -> AsmError {
+        match self.expansion {
+            Some((column, ref name)) => AsmError {
+                line: self.number,
+                column,
+                message: format!("in macro {}: {}", name, message.into()),
+            },
+            None => AsmError {
+                line: self.number,
+                column: offset + 1,
+                message: message.into(),
+            },
+        }
+    }
+}
+
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Line>,
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Number(i64),
+    Label(String, i64),
+}
+
+enum Statement {
+    Label(String, usize),
+    /// Numeric label, asserting the current address
+    Address(usize, usize),
+    Words(Vec<(Expr, usize)>),
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '.'
+}
+
+/// Splits `text` into comma separated fields, with their offsets.
+fn split_fields(text: &str, offset: usize) -> Vec<(&str, usize)> {
+    let mut fields = Vec::new();
+    let mut start = 0;
+    for part in text.split(',') {
+        let trimmed = part.trim_start();
+        let skipped = part.len() - trimmed.len();
+        fields.push((trimmed.trim_end(), offset + start + skipped));
+        start += part.len() + 1;
+    }
+    fields
+}
+
+/// Returns the first word of `text[offset..]` with its offset, and the
+/// offset of what follows it.
+fn first_word(text: &str, offset: usize) -> (&str, usize, usize) {
+    let rest = &text[offset..];
+    let start = offset + rest.len() - rest.trim_start().len();
+    let end = text[start..]
+        .find(char::is_whitespace)
+        .map_or(text.len(), |len| start + len);
+    (&text[start..end], start, end)
+}
+
+/// Reads the `label:` definitions at the start of the line. Returns them with
+/// their offsets, and the offset of the rest of the line.
+fn leading_labels(text: &str) -> (Vec<(&str, usize)>, usize) {
+    let mut labels = Vec::new();
+    let mut offset = 0;
+    loop {
+        let rest = &text[offset..];
+        let start = offset + rest.len() - rest.trim_start().len();
+        let end = text[start..]
+            .find(|c| !is_ident_char(c))
+            .map_or(text.len(), |len| start + len);
+        if end > start && text[end..].starts_with(':') {
+            labels.push((&text[start..end], start));
+            offset = end + 1;
+        } else {
+            return (labels, offset);
+        }
+    }
+}
+
+fn parse_expr(line: &Line, text: &str, offset: usize) -> Result<Expr, AsmError> {
+    let first = match text.chars().next() {
+        Some(c) => c,
+        None => return Err(line.error(offset, "missing value")),
+    };
+    if first.is_ascii_digit() || first == '-' || first == '+' {
+        return text
+            .parse()
+            .map(Expr::Number)
+            .map_err(|_| line.error(offset, format!("invalid number `{}`", text)));
+    }
+    let end = text.find(|c| !is_ident_char(c)).unwrap_or(text.len());
+    if end == 0 {
+        return Err(line.error(offset, format!("invalid value `{}`", text)));
+    }
+    let (name, rest) = text.split_at(end);
+    let shift = if rest.is_empty() {
+        0
+    } else if rest.starts_with('+') || rest.starts_with('-') {
+        rest.parse()
+            .map_err(|_| line.error(offset + end, format!("invalid label offset `{}`", rest)))?
+    } else {
+        return Err(line.error(offset + end, format!("unexpected `{}`", rest)));
+    };
+    Ok(Expr::Label(name.to_owned(), shift))
+}
+
+fn parse_operand(line: &Line, text: &str, offset: usize) -> Result<(Mode, Expr), AsmError> {
+    if text.starts_with('[') {
+        if !text.ends_with(']') {
+            return Err(line.error(offset + text.len(), "expected `]`"));
+        }
+        let inner = &text[1..text.len() - 1];
+        Ok((Mode::Position, parse_expr(line, inner.trim(), offset + 1)?))
+    } else if let Some(value) = text.strip_prefix('#') {
+        Ok((Mode::Immediate, parse_expr(line, value, offset + 1)?))
+    } else if text
+        .get(..2)
+        .is_some_and(|prefix| prefix.eq_ignore_ascii_case("rb"))
+    {
+        let rest = &text[2..];
+        let expr = if rest.is_empty() {
+            Expr::Number(0)
+        } else if let Some(shift) = rest.strip_prefix('+') {
+            parse_expr(line, shift, offset + 3)?
+        } else if rest.starts_with('-') {
+            parse_expr(line, rest, offset + 2)?
+        } else {
+            return Err(line.error(offset + 2, format!("unexpected `{}`", rest)));
+        };
+        Ok((Mode::Relative, expr))
+    } else {
+        Err(line.error(
+            offset,
+            format!(
+                "invalid operand `{}`, expected `[address]`, `#value` or `rb+offset`",
+                text
+            ),
+        ))
+    }
+}
+
+fn mode_digit(mode: Mode) -> i64 {
+    match mode {
+        Mode::Position => 0,
+        Mode::Immediate => 1,
+        Mode::Relative => 2,
+    }
+}
+
+fn parse_instruction(
+    line: &Line,
+    code: i64,
+    opcode: OpCode,
+    mnemonic_offset: usize,
+    operands: &str,
+    operands_offset: usize,
+) -> Result<Vec<(Expr, usize)>, AsmError> {
+    let fields = if operands.trim().is_empty() {
+        Vec::new()
+    } else {
+        split_fields(operands, operands_offset)
+    };
+    let expected = opcode.modes().len();
+    if fields.len() != expected {
+        return Err(line.error(
+            mnemonic_offset,
+            format!(
+                "{} takes {} operand(s), found {}",
+                opcode,
+                expected,
+                fields.len()
+            ),
+        ));
+    }
+    let mut word = code;
+    let mut words = vec![(Expr::Number(0), mnemonic_offset)];
+    for (i, (text, offset)) in fields.into_iter().enumerate() {
+        let (mode, expr) = parse_operand(line, text, offset)?;
+        if mode == Mode::Immediate && opcode.written_param() == Some(i) {
+            return Err(line.error(offset, "cannot write to an immediate operand"));
+        }
+        word += mode_digit(mode) * 10i64.pow(i as u32 + 2);
+        words.push((expr, offset));
+    }
+    words[0].0 = Expr::Number(word);
+    Ok(words)
+}
+
+fn parse_line(line: &Line, statements: &mut Vec<(Line, Statement)>) -> Result<(), AsmError> {
+    let text = &line.text;
+    let (labels, offset) = leading_labels(text);
+    for (label, label_offset) in labels {
+        let statement = if label.starts_with(|c: char| c.is_ascii_digit()) {
+            let address = label
+                .parse()
+                .map_err(|_| line.error(label_offset, format!("invalid address `{}`", label)))?;
+            Statement::Address(address, label_offset)
+        } else {
+            Statement::Label(label.to_owned(), label_offset)
+        };
+        statements.push((line.clone(), statement));
+    }
+    let (word, word_offset, end) = first_word(text, offset);
+    if word.is_empty() {
+        return Ok(());
+    }
+    let words = if word.eq_ignore_ascii_case("data") {
+        let mut words = Vec::new();
+        for (field, field_offset) in split_fields(&text[end..], end) {
+            words.push((parse_expr(line, field, field_offset)?, field_offset));
+        }
+        words
+    } else {
+        let opcode = OPCODES
+            .iter()
+            .map(|&code| (code, OpCode::from_word(&code).unwrap()))
+            .find(|(_, opcode)| opcode.mnemonic().eq_ignore_ascii_case(word));
+        match opcode {
+            Some((code, opcode)) => {
+                parse_instruction(line, code, opcode, word_offset, &text[end..], end)?
+            }
+            None => return Err(line.error(word_offset, format!("unknown mnemonic `{}`", word))),
+        }
+    };
+    statements.push((line.clone(), Statement::Words(words)));
+    Ok(())
+}
+
+/// Replaces the macro parameters in a body line, and `\@` by `unique`.
+fn substitute(text: &str, params: &[String], args: &[&str], unique: &str) -> String {
+    let text = text.replace("\\@", unique);
+    let mut result = String::new();
+    let mut rest = &text[..];
+    while !rest.is_empty() {
+        let end = rest.find(|c| !is_ident_char(c)).unwrap_or(rest.len());
+        if end == 0 {
+            let c = rest.chars().next().unwrap();
+            result.push(c);
+            rest = &rest[c.len_utf8()..];
+        } else {
+            let ident = &rest[..end];
+            match params.iter().position(|param| param == ident) {
+                Some(i) => result.push_str(args[i]),
+                None => result.push_str(ident),
+            }
+            rest = &rest[end..];
+        }
+    }
+    result
+}
+
+struct Expander {
+    macros: HashMap<String, Macro>,
+    expansions: usize,
+}
+
+impl Expander {
+    fn expand(&mut self, lines: Vec<Line>, depth: usize) -> Result<Vec<Line>, AsmError> {
+        let mut expanded = Vec::new();
+        let mut lines = lines.into_iter();
+        while let Some(line) = lines.next() {
+            let (_, offset) = leading_labels(&line.text);
+            let (word, word_offset, end) = first_word(&line.text, offset);
+            if word.eq_ignore_ascii_case("macro") {
+                if offset > 0 {
+                    return Err(line.error(0, "labels are not allowed on a macro definition"));
+                }
+                self.define(&line, end, &mut lines)?;
+            } else if word.eq_ignore_ascii_case("endm") {
+                return Err(line.error(word_offset, "`endm` outside of a macro definition"));
+            } else if self.macros.contains_key(&word.to_lowercase()) {
+                if depth >= MAX_EXPANSION_DEPTH {
+                    return Err(line.error(word_offset, "too many nested macro expansions"));
+                }
+                // Keep the labels preceding the invocation
+                expanded.push(Line {
+                    text: line.text[..word_offset].to_owned(),
+                    ..line.clone()
+                });
+                let body = self.invoke(&line, word, word_offset, end)?;
+                expanded.extend(self.expand(body, depth + 1)?);
+            } else {
+                expanded.push(line);
+            }
+        }
+        Ok(expanded)
+    }
+
+    fn define<L: Iterator<Item = Line>>(
+        &mut self,
+        line: &Line,
+        offset: usize,
+        lines: &mut L,
+    ) -> Result<(), AsmError> {
+        let (name, name_offset, end) = first_word(&line.text, offset);
+        if name.is_empty() || !name.chars().all(is_ident_char) {
+            return Err(line.error(name_offset, "expected a macro name"));
+        }
+        let reserved = ["data", "macro", "endm"]
+            .iter()
+            .any(|word| word.eq_ignore_ascii_case(name))
+            || OPCODES.iter().any(|&code| {
+                OpCode::from_word(&code)
+                    .unwrap()
+                    .mnemonic()
+                    .eq_ignore_ascii_case(name)
+            });
+        if reserved {
+            return Err(line.error(name_offset, format!("`{}` is reserved", name)));
+        }
+        let mut params = Vec::new();
+        if !line.text[end..].trim().is_empty() {
+            for (param, param_offset) in split_fields(&line.text[end..], end) {
+                if param.is_empty() || !param.chars().all(is_ident_char) {
+                    return Err(line.error(param_offset, "expected a parameter name"));
+                }
+                params.push(param.to_owned());
+            }
+        }
+        let mut body = Vec::new();
+        loop {
+            let body_line = match lines.next() {
+                Some(body_line) => body_line,
+                None => return Err(line.error(0, format!("unterminated macro `{}`", name))),
+            };
+            let (word, word_offset, _) = first_word(&body_line.text, 0);
+            if word.eq_ignore_ascii_case("endm") {
+                break;
+            } else if word.eq_ignore_ascii_case("macro") {
+                return Err(body_line.error(word_offset, "nested macro definition"));
+            }
+            body.push(body_line);
+        }
+        let key = name.to_lowercase();
+        if self.macros.contains_key(&key) {
+            return Err(line.error(name_offset, format!("macro `{}` already defined", name)));
+        }
+        self.macros.insert(key, Macro { params, body });
+        Ok(())
+    }
+
+    fn invoke(
+        &mut self,
+        line: &Line,
+        name: &str,
+        name_offset: usize,
+        end: usize,
+    ) -> Result<Vec<Line>, AsmError> {
+        let definition = &self.macros[&name.to_lowercase()];
+        let args: Vec<&str> = if line.text[end..].trim().is_empty() {
+            Vec::new()
+        } else {
+            split_fields(&line.text[end..], end)
+                .into_iter()
+                .map(|(arg, _)| arg)
+                .collect()
+        };
+        if args.len() != definition.params.len() {
+            return Err(line.error(
+                name_offset,
+                format!(
+                    "macro `{}` takes {} argument(s), found {}",
+                    name,
+                    definition.params.len(),
+                    args.len()
+                ),
+            ));
+        }
+        self.expansions += 1;
+        let unique = format!("_{}", self.expansions);
+        let expansion = line
+            .expansion
+            .clone()
+            .unwrap_or_else(|| (name_offset + 1, name.to_owned()));
+        Ok(definition
+            .body
+            .iter()
+            .map(|body_line| Line {
+                text: substitute(&body_line.text, &definition.params, &args, &unique),
+                number: line.number,
+                expansion: Some(expansion.clone()),
+            })
+            .collect())
+    }
+}
+
+/// Assembles the source into a program that can be loaded in a `Computer`.
+pub fn assemble(source: &str) -> Result<Vec<i64>, AsmError> {
+    let lines = source
+        .lines()
+        .enumerate()
+        .map(|(i, text)| Line {
+            text: text.split(';').next().unwrap().to_owned(),
+            number: i + 1,
+            expansion: None,
+        })
+        .collect();
+    let mut expander = Expander {
+        macros: HashMap::new(),
+        expansions: 0,
+    };
+    let mut statements = Vec::new();
+    for line in expander.expand(lines, 0)? {
+        parse_line(&line, &mut statements)?;
+    }
+
+    let mut labels = HashMap::new();
+    let mut address = 0;
+    for (line, statement) in statements.iter() {
+        match statement {
+            Statement::Label(label, offset) => {
+                if labels.insert(label.as_str(), address).is_some() {
+                    return Err(line.error(*offset, format!("label `{}` already defined", label)));
+                }
+            }
+            Statement::Address(expected, offset) => {
+                if *expected != address {
+                    return Err(line.error(
+                        *offset,
+                        format!("expected address {}, found {}", expected, address),
+                    ));
+                }
+            }
+            Statement::Words(words) => address += words.len(),
+        }
+    }
+
+    let mut program = Vec::with_capacity(address);
+    for (line, statement) in statements.iter() {
+        if let Statement::Words(words) = statement {
+            for (expr, offset) in words {
+                program.push(match expr {
+                    Expr::Number(value) => *value,
+                    Expr::Label(label, shift) => match labels.get(label.as_str()) {
+                        Some(&address) => {
+                            (address as i64).checked_add(*shift).ok_or_else(|| {
+                                line.error(*offset, format!("`{}` is out of range", label))
+                            })?
+                        }
+                        None => {
+                            return Err(line.error(*offset, format!("unknown label `{}`", label)))
+                        }
+                    },
+                });
+            }
+        }
+    }
+    Ok(program)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use computer::{Computer, InputQueue};
+    use disasm::disassemble;
+
+    const COUNTDOWN: &str = "
+; Outputs every number from the input down to 1
+macro DEC cell
+    ADD cell, #-1, cell
+endm
+
+        IN [counter]
+loop:   OUT [counter]
+        DEC [counter]
+        JNZ [counter], #loop
+        HLT
+counter: DATA 0
+";
+
+    #[test]
+    fn countdown() {
+        let program = assemble(COUNTDOWN).unwrap();
+        assert_eq!(
+            program,
+            vec![3, 12, 4, 12, 1001, 12, -1, 12, 1005, 12, 2, 99, 0]
+        );
+        let mut computer = Computer::new(program, InputQueue::new());
+        computer.push_input(3);
+        computer.run().unwrap();
+        assert_eq!(computer.outputs, vec![3, 2, 1]);
+    }
+
+    #[test]
+    fn roundtrip() {
+        let program = vec![
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99, 20, -7,
+        ];
+        assert_eq!(assemble(&disassemble(&program)).unwrap(), program);
+    }
+
+    #[test]
+    fn labels_and_macros() {
+        let source = "
+macro SKIP
+    JZ #0, #skip\\@
+    DATA 42
+skip\\@:
+endm
+macro TWICE op
+    op
+    op
+endm
+start: TWICE SKIP
+       data start, end-1, -3
+end:
+";
+        assert_eq!(
+            assemble(source).unwrap(),
+            vec![1106, 0, 4, 42, 1106, 0, 8, 42, 0, 10, -3]
+        );
+    }
+
+    #[test]
+    fn errors() {
+        let error = assemble("IN [0]\nADD #1, [2]\n").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 1));
+        assert_eq!(error.message, "ADD takes 3 operand(s), found 2");
+        assert_eq!(
+            error.to_string(),
+            "line 2, column 1: ADD takes 3 operand(s), found 2"
+        );
+
+        let error = assemble("  JNZ #1, #nowhere").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 11));
+        assert_eq!(error.message, "unknown label `nowhere`");
+
+        let error = assemble("x: MUL [x], [x], #3").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 18));
+
+        let error = assemble("a: HLT\n a: HLT").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 2));
+
+        let error = assemble("FOO [1]").unwrap_err();
+        assert_eq!(error.message, "unknown mnemonic `FOO`");
+
+        let error = assemble("macro BAD\n  OUT 3\nendm\n\n  BAD").unwrap_err();
+        assert_eq!((error.line, error.column), (5, 3));
+        assert_eq!(
+            error.message,
+            "in macro BAD: invalid operand `3`, expected `[address]`, `#value` or `rb+offset`"
+        );
+
+        let error = assemble("macro BAD\n  HLT\n").unwrap_err();
+        assert_eq!((error.line, error.column), (1, 1));
+
+        let error = assemble("HLT\nx: DATA x+9223372036854775807").unwrap_err();
+        assert_eq!((error.line, error.column), (2, 9));
+        assert_eq!(error.message, "`x` is out of range");
+
+        for (source, name) in &[
+            ("macro HLT\n  OUT #1\nendm", "HLT"),
+            ("macro data x\n  HLT\nendm", "data"),
+            ("macro Endm\nendm", "Endm"),
+            ("macro arb x\nendm", "arb"),
+        ] {
+            let error = assemble(source).unwrap_err();
+            assert_eq!((error.line, error.column), (1, 7));
+            assert_eq!(error.message, format!("`{}` is reserved", name));
+        }
+
+        let error = assemble("HLT\n3: HLT").unwrap_err();
+        assert_eq!(error.message, "expected address 3, found 1");
+
+        for source in &[
+            "OUT €",
+            "OUT r€",
+            "OUT rb€",
+            "OUT [€]",
+            "OUT #é",
+            "é: HLT",
+            "HLT €",
+        ] {
+            assert!(assemble(source).is_err(), "{} accepted", source);
+        }
+    }
+}
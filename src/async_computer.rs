@@ -0,0 +1,161 @@
+use super::computer::{Computer, ErrorKind, Event, InputQueue, IntcodeError};
+use futures::channel::mpsc::UnboundedSender;
+use futures::{Future, Stream};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// Instructions executed before giving control back to the executor, so that
+/// a long computation does not starve the other tasks.
+const INSTRUCTIONS_PER_POLL: usize = 10_000;
+
+/// A `Computer` reading its inputs from an async stream.
+///
+/// It is itself a stream of outputs, and waits for the input stream instead
+/// of failing when the program needs an input that has not arrived yet.
+pub struct AsyncComputer<S> {
+    pub computer: Computer<InputQueue>,
+    inputs: S,
+    done: bool,
+}
+
+impl<S: Stream<Item = i64> + Unpin> AsyncComputer<S> {
+    pub fn new(program: Vec<i64>, inputs: S) -> Self {
+        AsyncComputer {
+            computer: Computer::new(program, InputQueue::new()),
+            inputs,
+            done: false,
+        }
+    }
+
+    /// Returns a future running the program to completion and sending every
+    /// output to `outputs`. It resolves to the last output, or 0 if there was
+    /// none, like `Computer::run`.
+    ///
+    /// Outputs sent after the receiver was dropped are discarded.
+    pub fn connect(self, outputs: UnboundedSender<i64>) -> Connected<S> {
+        Connected {
+            machine: self,
+            outputs,
+            last_output: 0,
+        }
+    }
+}
+
+impl<S: Stream<Item = i64> + Unpin> Stream for AsyncComputer<S> {
+    type Item = Result<i64, IntcodeError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if this.done {
+            return Poll::Ready(None);
+        }
+        for _ in 0..INSTRUCTIONS_PER_POLL {
+            let result = match this.computer.run_op_code() {
+                Ok(None) => continue,
+                Ok(Some(Event::Output(value))) => Ok(value),
+                Ok(Some(Event::NeedsInput)) => match Pin::new(&mut this.inputs).poll_next(cx) {
+                    Poll::Ready(Some(value)) => {
+                        this.computer.push_input(value);
+                        continue;
+                    }
+                    Poll::Ready(None) => Err(this.computer.error(ErrorKind::OutOfInputs)),
+                    Poll::Pending => return Poll::Pending,
+                },
+                Ok(Some(Event::Halted)) => {
+                    this.done = true;
+                    return Poll::Ready(None);
+                }
+                Err(error) => Err(error),
+            };
+            this.done = result.is_err();
+            return Poll::Ready(Some(result));
+        }
+        cx.waker().wake_by_ref();
+        Poll::Pending
+    }
+}
+
+/// Future returned by `AsyncComputer::connect`.
+pub struct Connected<S> {
+    pub machine: AsyncComputer<S>,
+    outputs: UnboundedSender<i64>,
+    last_output: i64,
+}
+
+impl<S: Stream<Item = i64> + Unpin> Future for Connected<S> {
+    type Output = Result<i64, IntcodeError>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            match Pin::new(&mut this.machine).poll_next(cx) {
+                Poll::Ready(Some(Ok(value))) => {
+                    this.last_output = value;
+                    let _ = this.outputs.unbounded_send(value);
+                }
+                Poll::Ready(Some(Err(error))) => return Poll::Ready(Err(error)),
+                Poll::Ready(None) => return Poll::Ready(Ok(this.last_output)),
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amplifier::tests::FEEDBACK;
+    use futures::channel::mpsc::unbounded;
+    use futures::executor::block_on;
+    use futures::future::join_all;
+    use futures::stream::{self, StreamExt};
+
+    #[test]
+    fn stream() {
+        let program = vec![3, 9, 1002, 9, 2, 10, 4, 10, 99];
+        let machine = AsyncComputer::new(program.clone(), stream::iter(vec![21]));
+        let outputs: Vec<_> = block_on(machine.collect());
+        assert_eq!(outputs, vec![Ok(42)]);
+
+        let machine = AsyncComputer::new(program, stream::empty());
+        let outputs: Vec<_> = block_on(machine.collect());
+        assert_eq!(outputs.len(), 1);
+        assert_eq!(outputs[0].unwrap_err().kind, ErrorKind::OutOfInputs);
+    }
+
+    #[test]
+    fn thousand_machines() {
+        // Outputs its input plus one
+        let program = vec![3, 9, 1001, 9, 1, 9, 4, 9, 99, 0];
+        let (first, mut receiver) = unbounded();
+        let mut machines = Vec::new();
+        for _ in 0..1000 {
+            let (sender, next_receiver) = unbounded();
+            machines.push(AsyncComputer::new(program.clone(), receiver).connect(sender));
+            receiver = next_receiver;
+        }
+        first.unbounded_send(0).unwrap();
+        let results = block_on(join_all(machines));
+        assert_eq!(results.last(), Some(&Ok(1000)));
+        assert_eq!(block_on(receiver.next()), Some(1000));
+    }
+
+    #[test]
+    fn feedback_loop() {
+        let (senders, receivers): (Vec<_>, Vec<_>) = (0..5).map(|_| unbounded()).unzip();
+        for (sender, phase) in senders.iter().zip(&[9, 8, 7, 6, 5]) {
+            sender.unbounded_send(*phase).unwrap();
+        }
+        senders[0].unbounded_send(0).unwrap();
+        let machines: Vec<_> = receivers
+            .into_iter()
+            .enumerate()
+            .map(|(i, receiver)| {
+                let next = senders[(i + 1) % 5].clone();
+                AsyncComputer::new(FEEDBACK.to_vec(), receiver).connect(next)
+            })
+            .collect();
+        let results = block_on(join_all(machines));
+        assert_eq!(results[4], Ok(139629729));
+    }
+}
@@ -0,0 +1,252 @@
+extern crate adventofcode_2019;
+
+use adventofcode_2019::computer::{Computer, InputQueue, Stop, Watch};
+use adventofcode_2019::disasm::decode;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+use std::str::FromStr;
+
+const HISTORY_CAPACITY: usize = 1_000_000;
+
+const HELP: &str = "Commands:
+  step [n]                 execute n instructions (default 1)
+  continue                 run until a breakpoint, a watchpoint, input or halt
+  back [n]                 undo n instructions (default 1)
+  rewind <addr>            undo instructions until ip reaches addr
+  break <addr>             set a breakpoint
+  watch <addr> [r|w|rw]    set a watchpoint (default rw)
+  delete <addr>            remove the breakpoint and watchpoint at addr
+  mem <addr>[..<end>]      print memory
+  regs                     print ip, relative base and I/O state
+  disasm [addr] [n]        disassemble n instructions (default: 10 at ip)
+  input <n>...             queue input values
+  save <file>              save a snapshot of the machine
+  load <file>              restore a snapshot, keeping breakpoints and watchpoints
+  help                     print this message
+  quit                     exit the debugger";
+
+type Debuggee = Computer<InputQueue>;
+
+fn load(path: &str) -> Result<Vec<i64>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+    text.trim()
+        .split(',')
+        .map(|value| {
+            i64::from_str(value.trim()).map_err(|_| format!("{}: invalid value `{}`", path, value))
+        })
+        .collect()
+}
+
+fn parse<T: FromStr>(arg: Option<&str>, what: &str) -> Result<T, String> {
+    let arg = arg.ok_or_else(|| format!("missing {}", what))?;
+    T::from_str(arg).map_err(|_| format!("invalid {} `{}`", what, arg))
+}
+
+fn print_instruction(computer: &Debuggee, address: usize) -> usize {
+    // Cut short at the end of the address space
+    let window: Vec<i64> = (0..4)
+        .filter_map(|offset| address.checked_add(offset))
+        .map(|a| computer.memory[a])
+        .collect();
+    let marker = if address == computer.ip { "=>" } else { "  " };
+    match decode(&window, 0) {
+        Some(instruction) => {
+            println!("{} {:>6}: {}", marker, address, instruction);
+            instruction.size()
+        }
+        None => {
+            println!("{} {:>6}: DATA {}", marker, address, window[0]);
+            1
+        }
+    }
+}
+
+fn report(computer: &Debuggee, stop: Stop) {
+    match stop {
+        Stop::Step => (),
+        Stop::Breakpoint(address) => println!("Breakpoint at {}", address),
+        Stop::Watchpoint {
+            ip,
+            address,
+            access,
+        } => println!(
+            "Watchpoint: {:?} of {} by instruction {}",
+            access, address, ip
+        ),
+        Stop::NeedsInput => println!("Waiting for input"),
+        Stop::Halted => println!("Program halted"),
+    }
+    if stop != Stop::Halted {
+        print_instruction(computer, computer.ip);
+    }
+}
+
+fn run_command(computer: &mut Debuggee, line: &str) -> Result<bool, String> {
+    let mut words = line.split_whitespace();
+    let command = match words.next() {
+        Some(command) => command,
+        None => return Ok(true),
+    };
+    match command {
+        "step" | "s" => {
+            let count = match words.next() {
+                Some(count) => parse(Some(count), "count")?,
+                None => 1,
+            };
+            let mut stop = Stop::Step;
+            for _ in 0..count {
+                stop = computer.step().map_err(|e| e.to_string())?;
+                if stop != Stop::Step {
+                    break;
+                }
+            }
+            report(computer, stop);
+        }
+        "continue" | "c" => {
+            let stop = computer.run_until_break().map_err(|e| e.to_string())?;
+            report(computer, stop);
+        }
+        "back" => {
+            let count = match words.next() {
+                Some(count) => parse(Some(count), "count")?,
+                None => 1,
+            };
+            for _ in 0..count {
+                if !computer.step_back() {
+                    println!("Reached the start of the recorded history");
+                    break;
+                }
+            }
+            print_instruction(computer, computer.ip);
+        }
+        "rewind" => {
+            let address = parse(words.next(), "address")?;
+            if !computer.run_back_to(address) {
+                println!("Reached the start of the recorded history");
+            }
+            print_instruction(computer, computer.ip);
+        }
+        "break" | "b" => {
+            let address = parse(words.next(), "address")?;
+            computer.breakpoints.insert(address);
+        }
+        "watch" | "w" => {
+            let address = parse(words.next(), "address")?;
+            let watch = match words.next() {
+                Some("r") => Watch::Read,
+                Some("w") => Watch::Write,
+                Some("rw") | None => Watch::ReadWrite,
+                Some(other) => return Err(format!("invalid access `{}`", other)),
+            };
+            computer.watchpoints.insert(address, watch);
+        }
+        "delete" | "d" => {
+            let address = parse(words.next(), "address")?;
+            computer.breakpoints.remove(&address);
+            computer.watchpoints.remove(&address);
+        }
+        "mem" | "m" => {
+            let range = words.next().ok_or("missing range")?;
+            let mut bounds = range.splitn(2, "..");
+            let start: usize = parse(bounds.next(), "address")?;
+            let end = match bounds.next() {
+                Some(end) => parse(Some(end), "address")?,
+                None => start.checked_add(1).ok_or("address out of range")?,
+            };
+            for row in (start..end).step_by(8) {
+                let cells: Vec<String> = (row..end.min(row.saturating_add(8)))
+                    .map(|address| computer.memory[address].to_string())
+                    .collect();
+                println!("{:>6}: {}", row, cells.join(", "));
+            }
+        }
+        "regs" | "r" => {
+            println!("ip: {}", computer.ip);
+            println!("relative base: {}", computer.relative_base);
+            println!("pending inputs: {}", computer.inputs.len());
+            println!("outputs: {:?}", computer.outputs);
+        }
+        "disasm" | "x" => {
+            let mut next = Some(match words.next() {
+                Some(address) => parse(Some(address), "address")?,
+                None => computer.ip,
+            });
+            let count: usize = match words.next() {
+                Some(count) => parse(Some(count), "count")?,
+                None => 10,
+            };
+            for _ in 0..count {
+                let address = next.ok_or("reached the end of the address space")?;
+                next = address.checked_add(print_instruction(computer, address));
+            }
+        }
+        "input" | "i" => {
+            for word in words {
+                computer.push_input(parse(Some(word), "input")?);
+            }
+        }
+        "save" => {
+            let path = words.next().ok_or("missing file")?;
+            computer.save(path).map_err(|e| e.to_string())?;
+        }
+        "load" => {
+            let path = words.next().ok_or("missing file")?;
+            let mut restored = Computer::load(path).map_err(|e| e.to_string())?;
+            restored.breakpoints = computer.breakpoints.clone();
+            restored.watchpoints = computer.watchpoints.clone();
+            restored.enable_history(HISTORY_CAPACITY);
+            *computer = restored;
+            print_instruction(computer, computer.ip);
+        }
+        "help" | "h" => println!("{}", HELP),
+        "quit" | "q" => return Ok(false),
+        _ => return Err(format!("unknown command `{}`, try `help`", command)),
+    }
+    Ok(true)
+}
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 {
+        eprintln!("Usage: {} <program> [inputs...]", args[0]);
+        process::exit(1);
+    }
+    let program = load(&args[1]).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        process::exit(1);
+    });
+    let mut computer = Computer::new(program, InputQueue::new());
+    computer.enable_history(HISTORY_CAPACITY);
+    for arg in &args[2..] {
+        match i64::from_str(arg) {
+            Ok(value) => computer.push_input(value),
+            Err(_) => {
+                eprintln!("Invalid input `{}`", arg);
+                process::exit(1);
+            }
+        }
+    }
+
+    print_instruction(&computer, computer.ip);
+    let stdin = io::stdin();
+    let mut printed_outputs = 0;
+    loop {
+        print!("(intcode) ");
+        io::stdout().flush().unwrap();
+        let mut line = String::new();
+        if stdin.lock().read_line(&mut line).unwrap() == 0 {
+            break;
+        }
+        match run_command(&mut computer, &line) {
+            Ok(true) => (),
+            Ok(false) => break,
+            Err(e) => println!("Error: {}", e),
+        }
+        for output in computer.outputs.iter().skip(printed_outputs) {
+            println!("Output: {}", output);
+        }
+        printed_outputs = computer.outputs.len();
+    }
+}
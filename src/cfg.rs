@@ -0,0 +1,363 @@
+use super::computer::{Mode, OpCode};
+use super::disasm::{decode, Instruction};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fmt::Write;
+
+/// Straight-line run of reachable instructions, only entered at its start.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    /// One past the last cell of the last instruction
+    pub end: usize,
+    pub instructions: Vec<Instruction>,
+    /// Start addresses of the blocks control can flow to
+    pub successors: Vec<usize>,
+    /// Whether the block ends with a jump whose target is only known at
+    /// run time
+    pub indirect: bool,
+}
+
+/// Something suspicious found while analyzing a program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Finding {
+    /// Reachable word that is not a valid instruction
+    InvalidInstruction { address: usize },
+    /// Jump whose target is read from memory
+    IndirectJump { address: usize },
+    /// Cells from `start` to `end` are neither reachable code nor data
+    /// accessed by it
+    Unreachable { start: usize, end: usize },
+    /// The instruction at `writer` writes into the code at `address`
+    WrittenCode { address: usize, writer: usize },
+}
+
+impl Finding {
+    fn address(&self) -> usize {
+        match *self {
+            Finding::InvalidInstruction { address }
+            | Finding::IndirectJump { address }
+            | Finding::WrittenCode { address, .. } => address,
+            Finding::Unreachable { start, .. } => start,
+        }
+    }
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Finding::InvalidInstruction { address } => {
+                write!(f, "{}: invalid instruction", address)
+            }
+            Finding::IndirectJump { address } => write!(f, "{}: indirect jump", address),
+            Finding::Unreachable { start, end } => write!(f, "{}..{}: unreachable", start, end),
+            Finding::WrittenCode { address, writer } => {
+                write!(
+                    f,
+                    "{}: code written by the instruction at {}",
+                    address, writer
+                )
+            }
+        }
+    }
+}
+
+/// Where control can go after an instruction.
+struct Flow {
+    next: Option<usize>,
+    target: Option<usize>,
+    indirect: bool,
+}
+
+impl Flow {
+    fn branches(&self) -> bool {
+        self.target.is_some() || self.indirect
+    }
+}
+
+fn flow(instruction: &Instruction) -> Flow {
+    let next = Some(instruction.address + instruction.size());
+    let (taken_if_zero, modes) = match instruction.opcode {
+        OpCode::End => {
+            return Flow {
+                next: None,
+                target: None,
+                indirect: false,
+            }
+        }
+        OpCode::Jnz(modes) => (false, modes),
+        OpCode::Jz(modes) => (true, modes),
+        _ => {
+            return Flow {
+                next,
+                target: None,
+                indirect: false,
+            }
+        }
+    };
+    let (condition, target) = (instruction.params[0], instruction.params[1]);
+    // A jump on an immediate condition is either always or never taken
+    let (taken, not_taken) = match modes[0] {
+        Mode::Immediate => {
+            let taken = (condition == 0) == taken_if_zero;
+            (taken, !taken)
+        }
+        _ => (true, true),
+    };
+    let indirect = taken && modes[1] != Mode::Immediate;
+    Flow {
+        next: if not_taken { next } else { None },
+        target: if taken && !indirect && target >= 0 {
+            Some(target as usize)
+        } else {
+            None
+        },
+        indirect,
+    }
+}
+
+/// Control-flow graph of the code reachable from address 0.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Cfg {
+    pub blocks: BTreeMap<usize, BasicBlock>,
+    /// Sorted by address
+    pub findings: Vec<Finding>,
+}
+
+/// Walks the program from address 0, following the jumps with immediate
+/// targets, and builds its control-flow graph.
+///
+/// Parameters in position mode are assumed to be data addresses, to tell
+/// data from unreachable code and to find writes into the code. Accesses in
+/// relative mode and indirect jumps are not followed.
+pub fn analyze(program: &[i64]) -> Cfg {
+    let mut code = BTreeMap::new();
+    let mut leaders = BTreeSet::new();
+    let mut findings = Vec::new();
+    let mut invalid = BTreeSet::new();
+    let mut todo = vec![0];
+    leaders.insert(0);
+    while let Some(address) = todo.pop() {
+        if code.contains_key(&address) || invalid.contains(&address) {
+            continue;
+        }
+        let instruction = match decode(program, address) {
+            Some(instruction) => instruction,
+            None => {
+                invalid.insert(address);
+                findings.push(Finding::InvalidInstruction { address });
+                continue;
+            }
+        };
+        let flow = flow(&instruction);
+        if flow.indirect {
+            findings.push(Finding::IndirectJump { address });
+        }
+        if let Some(target) = flow.target {
+            leaders.insert(target);
+            todo.push(target);
+        }
+        if let Some(next) = flow.next {
+            if flow.branches() {
+                leaders.insert(next);
+            }
+            todo.push(next);
+        }
+        code.insert(address, instruction);
+    }
+
+    let mut blocks = BTreeMap::new();
+    for &start in leaders.iter().filter(|start| code.contains_key(start)) {
+        let mut block = BasicBlock {
+            start,
+            end: start,
+            instructions: Vec::new(),
+            successors: Vec::new(),
+            indirect: false,
+        };
+        let mut address = start;
+        loop {
+            let instruction = code[&address].clone();
+            let flow = flow(&instruction);
+            block.end = address + instruction.size();
+            block.instructions.push(instruction);
+            block.successors.extend(flow.target);
+            block.indirect = flow.indirect;
+            match flow.next {
+                Some(next)
+                    if flow.branches() || leaders.contains(&next) || !code.contains_key(&next) =>
+                {
+                    block.successors.push(next);
+                    break;
+                }
+                Some(next) => address = next,
+                None => break,
+            }
+        }
+        blocks.insert(start, block);
+    }
+
+    // Cells of reachable instructions, and cells accessed as data
+    let mut is_code = vec![false; program.len()];
+    let mut data = vec![false; program.len()];
+    for instruction in code.values() {
+        for cell in &mut is_code[instruction.address..instruction.address + instruction.size()] {
+            *cell = true;
+        }
+        for (&mode, &param) in instruction.opcode.modes().iter().zip(&instruction.params) {
+            if mode == Mode::Position && param >= 0 && (param as usize) < program.len() {
+                data[param as usize] = true;
+            }
+        }
+    }
+    for instruction in code.values() {
+        let written = match instruction.opcode.written_param() {
+            Some(param) if instruction.opcode.modes()[param] == Mode::Position => {
+                instruction.params[param]
+            }
+            _ => continue,
+        };
+        if written >= 0 && is_code.get(written as usize) == Some(&true) {
+            findings.push(Finding::WrittenCode {
+                address: written as usize,
+                writer: instruction.address,
+            });
+        }
+    }
+    let mut address = 0;
+    while address < program.len() {
+        let start = address;
+        while address < program.len()
+            && !is_code[address]
+            && !data[address]
+            && !invalid.contains(&address)
+        {
+            address += 1;
+        }
+        if address > start {
+            findings.push(Finding::Unreachable {
+                start,
+                end: address,
+            });
+        }
+        address += 1;
+    }
+
+    findings.sort_by_key(Finding::address);
+    Cfg { blocks, findings }
+}
+
+impl Cfg {
+    /// Exports the graph in Graphviz DOT format, one box per block listing
+    /// its instructions.
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::new();
+        writeln!(dot, "digraph cfg {{").unwrap();
+        writeln!(dot, "    node [shape=box, fontname=\"monospace\"];").unwrap();
+        let mut indirect = false;
+        let mut missing = BTreeSet::new();
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for instruction in &block.instructions {
+                write!(label, "{}: {}\\l", instruction.address, instruction).unwrap();
+            }
+            writeln!(dot, "    b{} [label=\"{}\"];", block.start, label).unwrap();
+            for successor in &block.successors {
+                writeln!(dot, "    b{} -> b{};", block.start, successor).unwrap();
+                if !self.blocks.contains_key(successor) {
+                    missing.insert(*successor);
+                }
+            }
+            if block.indirect {
+                indirect = true;
+                writeln!(dot, "    b{} -> indirect [style=dashed];", block.start).unwrap();
+            }
+        }
+        for address in missing {
+            writeln!(
+                dot,
+                "    b{} [label=\"{}: invalid\", color=red];",
+                address, address
+            )
+            .unwrap();
+        }
+        if indirect {
+            writeln!(dot, "    indirect [label=\"?\", shape=circle];").unwrap();
+        }
+        writeln!(dot, "}}").unwrap();
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asm::assemble;
+    use computer::tests::INPUT;
+
+    #[test]
+    fn blocks() {
+        let cfg = analyze(INPUT);
+        let starts: Vec<_> = cfg.blocks.keys().cloned().collect();
+        assert_eq!(starts, vec![0, 9, 16, 22, 31, 36, 46]);
+        assert_eq!(cfg.blocks[&0].end, 9);
+        assert_eq!(cfg.blocks[&0].successors, vec![22, 9]);
+        // Always taken
+        assert_eq!(cfg.blocks[&16].successors, vec![36]);
+        assert!(cfg.blocks[&46].successors.is_empty());
+        assert_eq!(
+            cfg.findings,
+            vec![
+                Finding::Unreachable { start: 19, end: 20 },
+                Finding::Unreachable { start: 45, end: 46 }
+            ]
+        );
+
+        let dot = cfg.to_dot();
+        assert!(dot.starts_with("digraph cfg {\n"));
+        assert!(dot.contains("    b0 -> b22;\n    b0 -> b9;\n"));
+        assert!(dot.contains("    b46 [label=\"46: HLT\\l\"];\n"));
+    }
+
+    #[test]
+    fn findings() {
+        let program = assemble(
+            "
+        IN [target]
+        ADD #1102, #0, [patch]
+patch:  ADD #2, #3, [x]
+        JZ [x], #bad
+        JNZ #1, [target]
+        MUL #1, #1, [x]
+bad:    DATA 42
+target: DATA 0
+x:      DATA 0
+",
+        )
+        .unwrap();
+        let cfg = analyze(&program);
+        assert_eq!(
+            cfg.findings,
+            vec![
+                Finding::WrittenCode {
+                    address: 6,
+                    writer: 2
+                },
+                Finding::IndirectJump { address: 13 },
+                Finding::Unreachable { start: 16, end: 20 },
+                Finding::InvalidInstruction { address: 20 },
+            ]
+        );
+        assert_eq!(
+            cfg.findings[0].to_string(),
+            "6: code written by the instruction at 2"
+        );
+        assert_eq!(cfg.blocks.len(), 2);
+        assert_eq!(cfg.blocks[&0].successors, vec![20, 13]);
+        assert!(cfg.blocks[&13].indirect);
+
+        let dot = cfg.to_dot();
+        assert!(dot.contains("    b13 -> indirect [style=dashed];\n"));
+        assert!(dot.contains("    b20 [label=\"20: invalid\", color=red];\n"));
+    }
+}
@@ -0,0 +1,128 @@
+use super::computer::{Computer, ErrorKind, Event, IntcodeError};
+use super::word::Word;
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::thread;
+
+/// Input source blocking on a channel: a machine reading from it waits for
+/// the next value. It runs out of inputs once every sender is dropped.
+pub struct ChannelInput<W = i64>(pub Receiver<W>);
+
+impl<W> Iterator for ChannelInput<W> {
+    type Item = W;
+
+    fn next(&mut self) -> Option<W> {
+        self.0.recv().ok()
+    }
+}
+
+impl<W: Word, I: Iterator<Item = W>> Computer<I, W> {
+    /// Runs the program until it halts, sending every output to `outputs`.
+    /// Returns the last output, or 0 if there was none, like `run`.
+    ///
+    /// Outputs sent after the receiver was dropped are discarded.
+    pub fn run_with_sender(&mut self, outputs: &Sender<W>) -> Result<W, IntcodeError<W>> {
+        let mut last_output = W::default();
+        loop {
+            match self.run_until_event()? {
+                Event::Output(value) => {
+                    last_output = value.clone();
+                    let _ = outputs.send(value);
+                }
+                Event::NeedsInput => return Err(self.error(ErrorKind::OutOfInputs)),
+                Event::Halted => return Ok(last_output),
+            }
+        }
+    }
+}
+
+/// Runs a copy of the program per phase setting, each on its own thread.
+/// Each machine receives its phase then the previous machine's outputs, the
+/// first one receiving `input`. With `feedback`, the last machine's outputs
+/// are also sent back to the first one.
+///
+/// Waits for every machine to stop, and returns the result of the last one.
+/// Machines waiting on each other in a loop would never stop.
+pub fn run_threaded_pipeline(
+    program: &[i64],
+    phases: &[i64],
+    input: i64,
+    feedback: bool,
+) -> Result<i64, IntcodeError> {
+    let (senders, receivers): (Vec<_>, Vec<_>) = phases.iter().map(|_| channel()).unzip();
+    for (sender, &phase) in senders.iter().zip(phases) {
+        sender.send(phase).unwrap();
+    }
+    match senders.first() {
+        Some(first) => first.send(input).unwrap(),
+        None => return Ok(input),
+    }
+    let (last_sender, _last_receiver) = channel();
+
+    let handles: Vec<_> = receivers
+        .into_iter()
+        .enumerate()
+        .map(|(i, receiver)| {
+            let outputs = match senders.get(i + 1) {
+                Some(next) => next.clone(),
+                None if feedback => senders[0].clone(),
+                None => last_sender.clone(),
+            };
+            let mut computer = Computer::new(program.to_vec(), ChannelInput(receiver));
+            thread::spawn(move || computer.run_with_sender(&outputs))
+        })
+        .collect();
+    // Only the machines hold senders now, so that they see their input
+    // channel close when the previous machine stops
+    drop(senders);
+    drop(last_sender);
+
+    let mut result = Ok(input);
+    for handle in handles {
+        result = handle.join().expect("Intcode machine thread panicked");
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use amplifier::tests::{CHAIN2, FEEDBACK};
+
+    #[test]
+    fn blocking_input() {
+        // Outputs the sum of its two inputs
+        let program = vec![3, 11, 3, 12, 1, 11, 12, 13, 4, 13, 99];
+        let (input, receiver) = channel();
+        let (sender, outputs) = channel();
+        let handle = thread::spawn(move || {
+            Computer::new(program, ChannelInput(receiver)).run_with_sender(&sender)
+        });
+        input.send(40).unwrap();
+        thread::sleep(std::time::Duration::from_millis(10));
+        input.send(2).unwrap();
+        assert_eq!(outputs.recv(), Ok(42));
+        assert_eq!(handle.join().unwrap(), Ok(42));
+
+        let program = vec![3, 11, 3, 12, 1, 11, 12, 13, 4, 13, 99];
+        let (input, receiver) = channel();
+        input.send(1).unwrap();
+        drop(input);
+        let (sender, _outputs) = channel();
+        let error = Computer::new(program, ChannelInput(receiver))
+            .run_with_sender(&sender)
+            .unwrap_err();
+        assert_eq!((error.ip, error.kind), (2, ErrorKind::OutOfInputs));
+    }
+
+    #[test]
+    fn pipelines() {
+        assert_eq!(
+            run_threaded_pipeline(CHAIN2, &[0, 1, 2, 3, 4], 0, false),
+            Ok(54321)
+        );
+        assert_eq!(
+            run_threaded_pipeline(FEEDBACK, &[9, 8, 7, 6, 5], 0, true),
+            Ok(139629729)
+        );
+    }
+}
@@ -0,0 +1,380 @@
+use super::computer::{to_address, Computer, ErrorKind, Event, IntcodeError, Mode, OpCode};
+use super::memory::Memory;
+use super::word::Word;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// How a `Computer` executes its program.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Engine {
+    /// Decodes and executes one instruction at a time
+    Interpreter,
+    /// Compiles straight-line runs of instructions into blocks of ops with
+    /// their parameters resolved in advance. Input, output and halt
+    /// instructions are left to the interpreter, as is anything the
+    /// compiler cannot prove valid, so that errors stay the same.
+    Compiled,
+}
+
+/// Instruction parameter, resolved at compile time.
+#[derive(Clone, Debug)]
+enum Operand<W> {
+    Immediate(W),
+    Position(usize),
+    Relative(i64),
+}
+
+impl<W: Word> Operand<W> {
+    fn new(mode: Mode, param: &W) -> Option<Self> {
+        Some(match mode {
+            Mode::Immediate => Operand::Immediate(param.clone()),
+            Mode::Position => Operand::Position(to_address(param).ok()?),
+            Mode::Relative => Operand::Relative(param.to_i64()?),
+        })
+    }
+}
+
+/// What to do after executing an op.
+enum Flow {
+    Next,
+    Jump(usize),
+    /// Go on with the next op, the one executed wrote at this address
+    Wrote(usize),
+}
+
+#[derive(Clone, Debug)]
+struct Op<W> {
+    address: usize,
+    op_code: OpCode,
+    operands: Vec<Operand<W>>,
+}
+
+/// Instructions from `start` to `end`, executed in a row unless one of them
+/// jumps.
+#[derive(Debug)]
+pub(crate) struct Block<W> {
+    start: usize,
+    end: usize,
+    ops: Vec<Op<W>>,
+}
+
+impl<W> Block<W> {
+    /// Whether the block depends on the cell at `address`. An empty block
+    /// still depends on the instruction it could not compile.
+    fn covers(&self, address: usize) -> bool {
+        address >= self.start && (address < self.end || address == self.start)
+    }
+}
+
+/// Compiles the block starting at `start`, which ends with the first jump
+/// or before the first instruction left to the interpreter.
+fn compile<W: Word>(memory: &Memory<W>, start: usize, limit: usize) -> Block<W> {
+    let mut ops = Vec::new();
+    let mut address = start;
+    while address < limit {
+        let op_code = match OpCode::from_word(&memory[address]) {
+            Ok(OpCode::Sav(_)) | Ok(OpCode::Prt(_)) | Ok(OpCode::End) | Err(_) => break,
+            Ok(op_code) => op_code,
+        };
+        let size = op_code.modes().len() + 1;
+        if address + size > limit
+            || op_code.written_param().map(|i| op_code.modes()[i]) == Some(Mode::Immediate)
+        {
+            break;
+        }
+        let operands: Option<Vec<_>> = op_code
+            .modes()
+            .iter()
+            .enumerate()
+            .map(|(i, &mode)| Operand::new(mode, &memory[address + 1 + i]))
+            .collect();
+        let operands = match operands {
+            Some(operands) => operands,
+            None => break,
+        };
+        ops.push(Op {
+            address,
+            op_code,
+            operands,
+        });
+        address += size;
+        if let OpCode::Jnz(_) | OpCode::Jz(_) = op_code {
+            break;
+        }
+    }
+    Block {
+        start,
+        end: address,
+        ops,
+    }
+}
+
+/// Compiled blocks of the contiguous part of a `Memory`, by start address.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct BlockCache<W> {
+    blocks: HashMap<usize, Arc<Block<W>>>,
+    /// Cells some block depends on
+    code: Vec<bool>,
+}
+
+impl<W: Word> BlockCache<W> {
+    /// Block starting at `start`, compiled from `memory` if needed. Blocks
+    /// do not go past `limit`.
+    pub(crate) fn get(&mut self, memory: &Memory<W>, start: usize, limit: usize) -> Arc<Block<W>> {
+        if let Some(block) = self.blocks.get(&start) {
+            return block.clone();
+        }
+        let block = Arc::new(compile(memory, start, limit));
+        let end = block.end.max(start + 1);
+        if self.code.len() < end {
+            self.code.resize(end, false);
+        }
+        for cell in &mut self.code[start..end] {
+            *cell = true;
+        }
+        self.blocks.insert(start, block.clone());
+        block
+    }
+
+    /// Drops the blocks depending on the cell at `address`.
+    pub(crate) fn invalidate(&mut self, address: usize) {
+        if let Some(cell) = self.code.get_mut(address) {
+            if *cell {
+                *cell = false;
+                self.blocks.retain(|_, block| !block.covers(address));
+            }
+        }
+    }
+}
+
+impl<W: Word, I: Iterator<Item = W>> Computer<I, W> {
+    pub fn engine(&self) -> Engine {
+        if self.memory.blocks.is_some() {
+            Engine::Compiled
+        } else {
+            Engine::Interpreter
+        }
+    }
+
+    /// Switches engines. Both give the same results, the compiled one is
+    /// faster on long runs but slower for short programs run many times, as
+    /// it compiles them again for every run.
+    ///
+    /// The compiled engine only runs when no watchpoint is set and history,
+    /// profiling, coverage, self-modification and loop detection are
+    /// disabled, as it does not report memory accesses and jumps.
+    pub fn set_engine(&mut self, engine: Engine) {
+        match engine {
+            Engine::Interpreter => self.memory.blocks = None,
+            Engine::Compiled => {
+                if self.memory.blocks.is_none() {
+                    self.memory.blocks = Some(BlockCache::default());
+                }
+            }
+        }
+    }
+
+    /// Runs the block at `ip`, or a single instruction with the interpreter
+    /// if there is no block there.
+    pub(crate) fn run_block(&mut self) -> Result<Option<Event<W>>, IntcodeError<W>> {
+        let block = match self.memory.block(self.ip) {
+            Some(block) if !block.ops.is_empty() => block,
+            _ => return self.run_op_code(),
+        };
+        self.halted = false;
+        for op in &block.ops {
+            self.ip = op.address;
+            if self.budget == Some(0) {
+                // Let the interpreter report the exhausted budget
+                return self.run_op_code();
+            }
+            let flow = self.run_op(op);
+            if let (Ok(_), Some(budget)) = (&flow, self.budget.as_mut()) {
+                *budget -= 1;
+            }
+            match flow {
+                Ok(Flow::Next) => (),
+                Ok(Flow::Jump(target)) => {
+                    self.ip = target;
+                    return Ok(None);
+                }
+                Ok(Flow::Wrote(address)) => {
+                    // The rest of the block may have been overwritten; cells
+                    // of the ops already executed do not matter
+                    let next = op.address + op.operands.len() + 1;
+                    if address >= next && address < block.end {
+                        self.ip = next;
+                        return Ok(None);
+                    }
+                }
+                // Let the interpreter report the error
+                Err(_) => return self.run_op_code(),
+            }
+        }
+        self.ip = block.end;
+        Ok(None)
+    }
+
+    fn operand_address(&self, operand: &Operand<W>) -> Result<usize, ErrorKind> {
+        match *operand {
+            Operand::Immediate(_) => Err(ErrorKind::ImmediateWrite),
+            Operand::Position(address) => Ok(address),
+            Operand::Relative(offset) => self
+                .relative_base
+                .checked_add(offset)
+                .ok_or(ErrorKind::OutOfBounds)
+                .and_then(|address| to_address(&address)),
+        }
+    }
+
+    fn operand_value(&self, operand: &Operand<W>) -> Result<W, ErrorKind> {
+        match operand {
+            Operand::Immediate(value) => Ok(value.clone()),
+            _ => Ok(self.memory.get(self.operand_address(operand)?)),
+        }
+    }
+
+    /// Executes an op. Nothing is changed when it fails.
+    fn run_op(&mut self, op: &Op<W>) -> Result<Flow, ErrorKind> {
+        let operands = &op.operands;
+        let result = match op.op_code {
+            OpCode::Add(_) => {
+                let (lhs, rhs) = (
+                    self.operand_value(&operands[0])?,
+                    self.operand_value(&operands[1])?,
+                );
+                self.arithmetic.add(&lhs, &rhs)?
+            }
+            OpCode::Mul(_) => {
+                let (lhs, rhs) = (
+                    self.operand_value(&operands[0])?,
+                    self.operand_value(&operands[1])?,
+                );
+                self.arithmetic.mul(&lhs, &rhs)?
+            }
+            OpCode::Leq(_) => W::from(
+                (self.operand_value(&operands[0])? < self.operand_value(&operands[1])?) as i64,
+            ),
+            OpCode::Eq(_) => W::from(
+                (self.operand_value(&operands[0])? == self.operand_value(&operands[1])?) as i64,
+            ),
+            OpCode::Jnz(_) | OpCode::Jz(_) => {
+                let zero = self.operand_value(&operands[0])? == W::default();
+                let jump = match op.op_code {
+                    OpCode::Jnz(_) => !zero,
+                    _ => zero,
+                };
+                return if jump {
+                    Ok(Flow::Jump(to_address(&self.operand_value(&operands[1])?)?))
+                } else {
+                    Ok(Flow::Next)
+                };
+            }
+            OpCode::Arb(_) => {
+                let offset = self.operand_value(&operands[0])?;
+                self.relative_base = offset
+                    .to_i64()
+                    .and_then(|offset| self.relative_base.checked_add(offset))
+                    .ok_or(ErrorKind::OutOfBounds)?;
+                return Ok(Flow::Next);
+            }
+            OpCode::Sav(_) | OpCode::Prt(_) | OpCode::End => {
+                unreachable!("{} is not compiled", op.op_code)
+            }
+        };
+        let address = self.operand_address(&operands[2])?;
+        self.memory[address] = result;
+        Ok(Flow::Wrote(address))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asm::assemble;
+    use computer::InputQueue;
+    use decode_cache::tests::PATCH;
+
+    /// Runs the program with both engines and checks they end in the same
+    /// state. Returns the outputs, or the error.
+    fn compare(program: &[i64], input: Option<i64>) -> Result<Vec<i64>, IntcodeError> {
+        let inputs: InputQueue = input.into_iter().collect();
+        let mut interpreted = Computer::new(program.to_vec(), inputs.clone());
+        let mut compiled = Computer::new(program.to_vec(), inputs);
+        compiled.set_engine(Engine::Compiled);
+        let result = interpreted.run();
+        assert_eq!(compiled.run(), result);
+        assert_eq!(compiled.ip, interpreted.ip);
+        assert_eq!(compiled.relative_base, interpreted.relative_base);
+        assert_eq!(compiled.memory.to_vec(), interpreted.memory.to_vec());
+        assert_eq!(compiled.outputs, interpreted.outputs);
+        result.map(|_| interpreted.outputs)
+    }
+
+    #[test]
+    fn loops() {
+        let countdown = assemble(
+            "
+        IN [n]
+loop:   ADD [n], #-1, [n]
+        MUL [n], #3, [t]
+        ADD [sum], [t], [sum]
+        JNZ [n], #loop
+        OUT [sum]
+        HLT
+n:      DATA 0
+t:      DATA 0
+sum:    DATA 0
+",
+        )
+        .unwrap();
+        assert_eq!(
+            compare(&countdown, Some(1000)),
+            Ok(vec![3 * 999 * 1000 / 2])
+        );
+
+        let mut computer = Computer::new(countdown, std::iter::once(1000));
+        assert_eq!(computer.engine(), Engine::Interpreter);
+        computer.set_engine(Engine::Compiled);
+        assert_eq!(computer.engine(), Engine::Compiled);
+        assert_eq!(computer.run(), Ok(3 * 999 * 1000 / 2));
+        let quine = [
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        assert_eq!(compare(&quine, None), Ok(quine.to_vec()));
+    }
+
+    #[test]
+    fn self_modifying() {
+        // The first instruction turns the second one into a multiplication
+        let patch_next = assemble(
+            "
+        ADD #1102, #0, [next]
+next:   ADD #2, #3, [result]
+        OUT [result]
+        HLT
+result: DATA 0
+",
+        )
+        .unwrap();
+        assert_eq!(compare(&patch_next, None), Ok(vec![6]));
+
+        // The patched instruction was already compiled and executed
+        let patch_back = assemble(PATCH).unwrap();
+        assert_eq!(compare(&patch_back, None), Ok(vec![5, 6]));
+    }
+
+    #[test]
+    fn errors() {
+        let error = compare(&[1101, 1, 1, 5, 42], None).unwrap_err();
+        assert_eq!((error.ip, error.kind), (4, ErrorKind::UnknownOpCode));
+        let error = compare(&[1, 0, 0, 0, 1005, 0, -3], None).unwrap_err();
+        assert_eq!(error.kind, ErrorKind::NegativeAddress(-3));
+        let error = compare(&[109, -5, 1201, 2, 0, 0, 99], None).unwrap_err();
+        assert_eq!((error.ip, error.kind), (2, ErrorKind::NegativeAddress(-3)));
+        let error = compare(&[1101, i64::MAX, 1, 0, 99], None).unwrap_err();
+        assert_eq!((error.ip, error.kind), (0, ErrorKind::Overflow));
+        let error = compare(&[1105, 1, 7, 99, 3, 0, 99], None).unwrap_err();
+        assert_eq!((error.ip, error.kind), (7, ErrorKind::UnknownOpCode));
+    }
+}
@@ -1,161 +1,719 @@
+use super::coverage::Coverage;
+use super::cycle::CycleDetector;
+use super::history::History;
+use super::memory::Memory;
+use super::profile::Profile;
+use super::smc::SmcDetector;
+use super::word::Word;
+use std::collections::{vec_deque, HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
-use std::iter::Iterator;
-enum OpCode {
-    Add([bool; 3]),
-    Mul([bool; 3]),
-    Sav([bool; 1]),
-    Prt([bool; 1]),
-    Jnz([bool; 2]),
-    Jz([bool; 2]),
-    Leq([bool; 3]),
-    Eq([bool; 3]),
+use std::error::Error;
+use std::fmt;
+use std::iter::{FromIterator, Iterator};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Mode {
+    Position,
+    Immediate,
+    Relative,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OpCode {
+    Add([Mode; 3]),
+    Mul([Mode; 3]),
+    Sav([Mode; 1]),
+    Prt([Mode; 1]),
+    Jnz([Mode; 2]),
+    Jz([Mode; 2]),
+    Leq([Mode; 3]),
+    Eq([Mode; 3]),
+    Arb([Mode; 1]),
     End,
-    Err,
 }
 
-fn parse_modes<const N: usize>(mut value: isize, mut params: [bool; N]) -> [bool; N] {
+fn parse_modes<const N: usize>(
+    mut value: isize,
+    mut params: [Mode; N],
+) -> Result<[Mode; N], ErrorKind> {
     let mut mode;
     for param in params.iter_mut() {
         mode = (value) % 10;
-        assert!(mode <= 1, "Invalid mode: {}", mode);
-        *param = mode == 1;
+        *param = match mode {
+            0 => Mode::Position,
+            1 => Mode::Immediate,
+            2 => Mode::Relative,
+            _ => return Err(ErrorKind::InvalidMode(mode)),
+        };
         value /= 10;
     }
-    params
+    Ok(params)
 }
 
 impl OpCode {
-    fn from_isize(value: isize) -> OpCode {
+    pub fn from_isize(value: isize) -> Result<OpCode, ErrorKind> {
         if value < 0 {
-            Self::Err
-        } else {
-            match value % 100 {
-                1 => Self::Add(parse_modes(value / 100, [false; 3])),
-                2 => Self::Mul(parse_modes(value / 100, [false; 3])),
-                3 => Self::Sav(parse_modes(value / 100, [false; 1])),
-                4 => Self::Prt(parse_modes(value / 100, [false; 1])),
-                5 => Self::Jnz(parse_modes(value / 100, [false; 2])),
-                6 => Self::Jz(parse_modes(value / 100, [false; 2])),
-                7 => Self::Leq(parse_modes(value / 100, [false; 3])),
-                8 => Self::Eq(parse_modes(value / 100, [false; 3])),
-                99 => Self::End,
-                _ => Self::Err,
+            return Err(ErrorKind::UnknownOpCode);
+        }
+        Ok(match value % 100 {
+            1 => Self::Add(parse_modes(value / 100, [Mode::Position; 3])?),
+            2 => Self::Mul(parse_modes(value / 100, [Mode::Position; 3])?),
+            3 => Self::Sav(parse_modes(value / 100, [Mode::Position; 1])?),
+            4 => Self::Prt(parse_modes(value / 100, [Mode::Position; 1])?),
+            5 => Self::Jnz(parse_modes(value / 100, [Mode::Position; 2])?),
+            6 => Self::Jz(parse_modes(value / 100, [Mode::Position; 2])?),
+            7 => Self::Leq(parse_modes(value / 100, [Mode::Position; 3])?),
+            8 => Self::Eq(parse_modes(value / 100, [Mode::Position; 3])?),
+            9 => Self::Arb(parse_modes(value / 100, [Mode::Position; 1])?),
+            99 => Self::End,
+            _ => return Err(ErrorKind::UnknownOpCode),
+        })
+    }
+
+    /// Decodes an instruction word of any type.
+    pub fn from_word<W: Word>(value: &W) -> Result<OpCode, ErrorKind> {
+        let value = value
+            .to_i64()
+            .and_then(|value| isize::try_from(value).ok())
+            .ok_or(ErrorKind::UnknownOpCode)?;
+        Self::from_isize(value)
+    }
+
+    /// Modes of the instruction parameters, in order.
+    pub fn modes(&self) -> &[Mode] {
+        match self {
+            OpCode::Add(modes) | OpCode::Mul(modes) | OpCode::Leq(modes) | OpCode::Eq(modes) => {
+                &modes[..]
             }
+            OpCode::Jnz(modes) | OpCode::Jz(modes) => &modes[..],
+            OpCode::Sav(modes) | OpCode::Prt(modes) | OpCode::Arb(modes) => &modes[..],
+            OpCode::End => &[],
+        }
+    }
+
+    /// Index of the parameter the instruction writes to, if any.
+    pub fn written_param(&self) -> Option<usize> {
+        match self {
+            OpCode::Add(_) | OpCode::Mul(_) | OpCode::Leq(_) | OpCode::Eq(_) => Some(2),
+            OpCode::Sav(_) => Some(0),
+            _ => None,
+        }
+    }
+
+    pub fn mnemonic(&self) -> &'static str {
+        match self {
+            OpCode::Add(_) => "ADD",
+            OpCode::Mul(_) => "MUL",
+            OpCode::Sav(_) => "IN",
+            OpCode::Prt(_) => "OUT",
+            OpCode::Jnz(_) => "JNZ",
+            OpCode::Jz(_) => "JZ",
+            OpCode::Leq(_) => "LT",
+            OpCode::Eq(_) => "EQ",
+            OpCode::Arb(_) => "ARB",
+            OpCode::End => "HLT",
+        }
+    }
+}
+
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.mnemonic())
+    }
+}
+
+/// What went wrong while executing an instruction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorKind {
+    UnknownOpCode,
+    InvalidMode(isize),
+    ImmediateWrite,
+    NegativeAddress(i64),
+    OutOfInputs,
+    OutOfBounds,
+    /// The result of an arithmetic instruction does not fit in a word
+    Overflow,
+    /// The instruction budget ran out before this instruction
+    BudgetExhausted,
+    /// This backward jump leads back to a state already reached without
+    /// reading any input in between: the program never halts
+    InfiniteLoop,
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnknownOpCode => write!(f, "unknown opcode"),
+            ErrorKind::InvalidMode(mode) => write!(f, "invalid parameter mode {}", mode),
+            ErrorKind::ImmediateWrite => write!(f, "write to a parameter in immediate mode"),
+            ErrorKind::NegativeAddress(address) => write!(f, "negative address {}", address),
+            ErrorKind::OutOfInputs => write!(f, "no more inputs"),
+            ErrorKind::OutOfBounds => write!(f, "address out of bounds"),
+            ErrorKind::Overflow => write!(f, "arithmetic overflow"),
+            ErrorKind::BudgetExhausted => write!(f, "instruction budget exhausted"),
+            ErrorKind::InfiniteLoop => write!(f, "infinite loop"),
         }
     }
 }
 
-pub struct Computer<I: Iterator<Item = isize>> {
+/// Error raised by a `Computer`, located at the instruction that failed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IntcodeError<W = i64> {
+    /// Address of the failing instruction
     pub ip: usize,
-    pub memory: Vec<isize>,
+    /// Raw instruction word at `ip`
+    pub instruction: W,
+    /// Opcode decoded from the instruction word
+    pub opcode: W,
+    pub kind: ErrorKind,
+}
+
+impl<W: fmt::Display> fmt::Display for IntcodeError<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} at ip {} (instruction {}, opcode {})",
+            self.kind, self.ip, self.instruction, self.opcode
+        )
+    }
+}
+
+impl<W: fmt::Debug + fmt::Display> Error for IntcodeError<W> {}
+
+/// Why `Computer::run_until_event` handed control back to its caller.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event<W = i64> {
+    /// The program wants to read but no input is available; `ip` still
+    /// points at the input instruction so the run can be resumed.
+    NeedsInput,
+    Output(W),
+    Halted,
+}
+
+/// Input source that can be fed one value at a time while the program runs.
+#[derive(Clone, Debug, Default)]
+pub struct InputQueue<W = i64>(VecDeque<W>);
+
+impl<W> InputQueue<W> {
+    pub fn new() -> Self {
+        InputQueue(VecDeque::new())
+    }
+
+    pub fn push(&mut self, value: W) {
+        self.0.push_back(value)
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> vec_deque::Iter<'_, W> {
+        self.0.iter()
+    }
+
+    /// Puts back an input so that it is the next one read.
+    pub fn unread(&mut self, value: W) {
+        self.0.push_front(value)
+    }
+}
+
+impl<W> Iterator for InputQueue<W> {
+    type Item = W;
+
+    fn next(&mut self) -> Option<W> {
+        self.0.pop_front()
+    }
+}
+
+impl<W> FromIterator<W> for InputQueue<W> {
+    fn from_iter<T: IntoIterator<Item = W>>(iter: T) -> Self {
+        InputQueue(iter.into_iter().collect())
+    }
+}
+
+/// How `Add` and `Mul` instructions handle results that do not fit in a
+/// word.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Arithmetic {
+    Wrapping,
+    /// Overflows are `ErrorKind::Overflow` errors
+    #[default]
+    Checked,
+    Saturating,
+}
+
+impl Arithmetic {
+    pub(crate) fn add<W: Word>(self, lhs: &W, rhs: &W) -> Result<W, ErrorKind> {
+        match self {
+            Arithmetic::Wrapping => Ok(lhs.wrapping_add(rhs)),
+            Arithmetic::Checked => lhs.checked_add(rhs).ok_or(ErrorKind::Overflow),
+            Arithmetic::Saturating => Ok(lhs.saturating_add(rhs)),
+        }
+    }
+
+    pub(crate) fn mul<W: Word>(self, lhs: &W, rhs: &W) -> Result<W, ErrorKind> {
+        match self {
+            Arithmetic::Wrapping => Ok(lhs.wrapping_mul(rhs)),
+            Arithmetic::Checked => lhs.checked_mul(rhs).ok_or(ErrorKind::Overflow),
+            Arithmetic::Saturating => Ok(lhs.saturating_mul(rhs)),
+        }
+    }
+}
+
+/// Kind of memory access.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// Accesses a watchpoint stops on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Watch {
+    Read,
+    Write,
+    ReadWrite,
+}
+
+impl Watch {
+    fn matches(self, access: Access) -> bool {
+        self == Watch::ReadWrite
+            || (self == Watch::Read && access == Access::Read)
+            || (self == Watch::Write && access == Access::Write)
+    }
+}
+
+/// Why `Computer::step` or `Computer::run_until_break` stopped.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Stop {
+    /// A single instruction was executed
+    Step,
+    /// `ip` reached a breakpoint; the instruction there was not executed yet
+    Breakpoint(usize),
+    /// The instruction at `ip` accessed a watched address
+    Watchpoint {
+        ip: usize,
+        address: usize,
+        access: Access,
+    },
+    NeedsInput,
+    Halted,
+}
+
+pub(crate) fn to_address<W: Word>(value: &W) -> Result<usize, ErrorKind> {
+    let value = value.to_i64().ok_or(ErrorKind::OutOfBounds)?;
+    usize::try_from(value).map_err(|_| {
+        if value < 0 {
+            ErrorKind::NegativeAddress(value)
+        } else {
+            ErrorKind::OutOfBounds
+        }
+    })
+}
+
+/// Intcode machine working on words of type `W`, `i64` by default.
+pub struct Computer<I, W = i64>
+where
+    I: Iterator<Item = W>,
+{
+    pub ip: usize,
+    pub relative_base: i64,
+    pub memory: Memory<W>,
     pub inputs: I,
-    pub outputs: Vec<isize>,
+    pub outputs: Vec<W>,
+    pub breakpoints: HashSet<usize>,
+    pub watchpoints: HashMap<usize, Watch>,
+    pub arithmetic: Arithmetic,
+    /// Instructions left to execute before failing with
+    /// `ErrorKind::BudgetExhausted`, without limit when None
+    pub budget: Option<u64>,
+    watch_hit: Option<(usize, Access)>,
+    pub(crate) history: Option<History<W>>,
+    pub(crate) smc: Option<SmcDetector>,
+    pub(crate) cycles: Option<CycleDetector<W>>,
+    pub(crate) profile: Option<Profile>,
+    pub(crate) coverage: Option<Coverage>,
+    /// Set once iterating over outputs returned an error
+    iteration_failed: bool,
+    /// Set once the program halted, until another instruction executes
+    pub(crate) halted: bool,
 }
 
-impl<I: Iterator<Item = isize>> Computer<I> {
-    fn read_with_mode(&mut self, is_immediate: bool) -> Result<isize, &'static str> {
+impl<I: Iterator<Item = i64>> Computer<I> {
+    pub fn new(memory: Vec<i64>, inputs: I) -> Self {
+        Self::with_memory(memory, inputs)
+    }
+}
+
+impl<W: Word, I: Iterator<Item = W>> Computer<I, W> {
+    /// Like `new`, for any word type.
+    pub fn with_memory<M: Into<Memory<W>>>(memory: M, inputs: I) -> Self {
+        Computer {
+            ip: 0,
+            relative_base: 0,
+            memory: memory.into(),
+            inputs,
+            outputs: Vec::new(),
+            breakpoints: HashSet::new(),
+            watchpoints: HashMap::new(),
+            arithmetic: Arithmetic::default(),
+            budget: None,
+            watch_hit: None,
+            history: None,
+            smc: None,
+            cycles: None,
+            profile: None,
+            coverage: None,
+            iteration_failed: false,
+            halted: false,
+        }
+    }
+
+    /// Builds an error located at the current instruction.
+    pub(crate) fn error(&self, kind: ErrorKind) -> IntcodeError<W> {
+        let instruction = self.memory.get(self.ip);
+        IntcodeError {
+            ip: self.ip,
+            opcode: instruction.clone() % W::from(100),
+            instruction,
+            kind,
+        }
+    }
+
+    /// Address designated by the parameter at `ip`, for a non-immediate mode.
+    fn address(&self, mode: Mode) -> Result<usize, ErrorKind> {
+        let param = &self.memory[self.ip];
+        match mode {
+            Mode::Relative => param
+                .to_i64()
+                .and_then(|param| self.relative_base.checked_add(param))
+                .ok_or(ErrorKind::OutOfBounds)
+                .and_then(|pos| to_address(&pos)),
+            _ => to_address(param),
+        }
+    }
+
+    fn read_with_mode(&mut self, mode: Mode) -> Result<W, ErrorKind> {
         self.ip += 1;
-        if is_immediate {
-            Ok(self.memory[self.ip])
-        } else {
-            let pos = usize::try_from(self.memory[self.ip])
-                .map_err(|_| "Reading from a negative pointer!")?;
-            Ok(self.memory[pos])
+        if mode == Mode::Immediate {
+            return Ok(self.memory.get(self.ip));
+        }
+        let pos = self.address(mode)?;
+        self.watch(pos, Access::Read);
+        if let Some(profile) = self.profile.as_mut() {
+            *profile.reads.entry(pos).or_insert(0) += 1;
         }
+        Ok(self.memory.get(pos))
     }
 
-    fn store_with_mode(&mut self, is_immediate: bool, value: isize) -> Result<(), &'static str> {
+    fn store_with_mode(&mut self, mode: Mode, value: W) -> Result<(), ErrorKind> {
+        let pos = self.write_address(mode)?;
+        self.store(pos, value);
+        Ok(())
+    }
+
+    /// Address the parameter at `ip` designates for writing.
+    fn write_address(&mut self, mode: Mode) -> Result<usize, ErrorKind> {
         self.ip += 1;
-        assert!(!is_immediate, "Trying to write while in immediate mode");
-        let pos = usize::try_from(self.memory[self.ip])
-            .map_err(|_| "Storing into a negative pointer!")?;
+        if mode == Mode::Immediate {
+            return Err(ErrorKind::ImmediateWrite);
+        }
+        self.address(mode)
+    }
+
+    fn store(&mut self, pos: usize, value: W) {
+        self.watch(pos, Access::Write);
+        if let Some(profile) = self.profile.as_mut() {
+            *profile.writes.entry(pos).or_insert(0) += 1;
+        }
+        if let Some(smc) = self.smc.as_mut() {
+            smc.write(pos);
+        }
+        if let Some(cycles) = self.cycles.as_mut() {
+            cycles.write(pos, &self.memory[pos], &value);
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.current().write = Some((pos, self.memory.get(pos)));
+        }
         self.memory[pos] = value;
-        Ok(())
     }
 
-    fn run_op_code(&mut self) -> Result<bool, &'static str> {
-        let op_code = OpCode::from_isize(self.memory[self.ip]);
+    fn watch(&mut self, address: usize, access: Access) {
+        if self.watchpoints.is_empty() || self.watch_hit.is_some() {
+            return;
+        }
+        if let Some(watch) = self.watchpoints.get(&address) {
+            if watch.matches(access) {
+                self.watch_hit = Some((address, access));
+            }
+        }
+    }
+
+    /// Executes the instruction at `ip`. On error `ip` is left on the failing
+    /// instruction.
+    pub(crate) fn run_op_code(&mut self) -> Result<Option<Event<W>>, IntcodeError<W>> {
+        let ip = self.ip;
+        if self.budget == Some(0) {
+            return Err(self.error(ErrorKind::BudgetExhausted));
+        }
+        if let Some(history) = self.history.as_mut() {
+            history.begin(ip, self.relative_base);
+        }
+        let result = self.execute();
+        if let Some(history) = self.history.as_mut() {
+            match result {
+                // Nothing was executed
+                Ok(Some(Event::NeedsInput)) | Ok(Some(Event::Halted)) | Err(_) => history.cancel(),
+                _ => history.commit(),
+            }
+        }
+        if let (Ok(None), Some(budget)) | (Ok(Some(Event::Output(_))), Some(budget)) =
+            (&result, self.budget.as_mut())
+        {
+            *budget -= 1;
+        }
+        result.map_err(|kind| {
+            self.ip = ip;
+            self.error(kind)
+        })
+    }
+
+    /// Stores an output produced by the last executed instruction.
+    fn store_output(&mut self, value: W) {
+        self.outputs.push(value);
+        if let Some(history) = self.history.as_mut() {
+            history.current().output = true;
+        }
+    }
+
+    fn execute(&mut self) -> Result<Option<Event<W>>, ErrorKind> {
+        let start = self.ip;
+        let op_code = self.memory.decode(self.ip)?;
+        if let Some(smc) = self.smc.as_mut() {
+            smc.execute(self.ip, op_code.modes().len() + 1);
+        }
         let mut jumped = false;
+        let mut event = None;
         match op_code {
             OpCode::Add(modes) => {
-                let result = self.read_with_mode(modes[0])? + self.read_with_mode(modes[1])?;
+                let lhs = self.read_with_mode(modes[0])?;
+                let rhs = self.read_with_mode(modes[1])?;
+                let result = self.arithmetic.add(&lhs, &rhs)?;
                 self.store_with_mode(modes[2], result)?
             }
             OpCode::Mul(modes) => {
-                let result = self.read_with_mode(modes[0])? * self.read_with_mode(modes[1])?;
+                let lhs = self.read_with_mode(modes[0])?;
+                let rhs = self.read_with_mode(modes[1])?;
+                let result = self.arithmetic.mul(&lhs, &rhs)?;
                 self.store_with_mode(modes[2], result)?
             }
             OpCode::Sav(modes) => {
-                let result = self.inputs.next().expect("Need more inputs!");
-                self.store_with_mode(modes[0], result)?
+                // An invalid destination must not cost an input
+                let pos = self.write_address(modes[0])?;
+                let result = match self.inputs.next() {
+                    Some(value) => value,
+                    None => {
+                        self.ip = start;
+                        return Ok(Some(Event::NeedsInput));
+                    }
+                };
+                if let Some(history) = self.history.as_mut() {
+                    history.current().input = Some(result.clone());
+                }
+                if let Some(cycles) = self.cycles.as_mut() {
+                    cycles.reset();
+                }
+                self.store(pos, result)
             }
             OpCode::Prt(modes) => {
                 let result = self.read_with_mode(modes[0])?;
-                self.outputs.push(result)
+                event = Some(Event::Output(result))
             }
             OpCode::Jnz(modes) => {
                 let result = self.read_with_mode(modes[0])?;
                 let jump = self.read_with_mode(modes[1])?;
-                if result != 0 {
-                    self.ip =
-                        usize::try_from(jump).map_err(|_| "Jumping into a negative pointer!")?;
+                if result != W::default() {
+                    self.ip = to_address(&jump)?;
                     jumped = true;
                 }
             }
             OpCode::Jz(modes) => {
                 let result = self.read_with_mode(modes[0])?;
                 let jump = self.read_with_mode(modes[1])?;
-                if result == 0 {
-                    self.ip =
-                        usize::try_from(jump).map_err(|_| "Jumping into a negative pointer!")?;
+                if result == W::default() {
+                    self.ip = to_address(&jump)?;
                     jumped = true;
                 }
             }
             OpCode::Leq(modes) => {
                 let lhs = self.read_with_mode(modes[0])?;
                 let rhs = self.read_with_mode(modes[1])?;
-                if lhs < rhs {
-                    self.store_with_mode(modes[2], 1)?
-                } else {
-                    self.store_with_mode(modes[2], 0)?
-                }
+                self.store_with_mode(modes[2], W::from((lhs < rhs) as i64))?
             }
             OpCode::Eq(modes) => {
                 let lhs = self.read_with_mode(modes[0])?;
                 let rhs = self.read_with_mode(modes[1])?;
-                if lhs == rhs {
-                    self.store_with_mode(modes[2], 1)?
-                } else {
-                    self.store_with_mode(modes[2], 0)?
+                self.store_with_mode(modes[2], W::from((lhs == rhs) as i64))?
+            }
+            OpCode::Arb(modes) => {
+                let offset = self.read_with_mode(modes[0])?;
+                self.relative_base = offset
+                    .to_i64()
+                    .and_then(|offset| self.relative_base.checked_add(offset))
+                    .ok_or(ErrorKind::OutOfBounds)?;
+            }
+            OpCode::End => {
+                // Polling a halted machine again executes nothing new
+                if !self.halted {
+                    if let Some(profile) = self.profile.as_mut() {
+                        profile.execute(start, op_code);
+                    }
+                    if let Some(coverage) = self.coverage.as_mut() {
+                        coverage.execute(start, op_code, false);
+                    }
                 }
+                self.halted = true;
+                return Ok(Some(Event::Halted));
             }
-            OpCode::End => return Ok(false),
-            OpCode::Err => return Err("Read a wrong opcode"),
         }
         if !jumped {
             self.ip += 1
+        } else if self.ip <= start {
+            if let Some(cycles) = self.cycles.as_mut() {
+                if cycles.repeated(self.ip, self.relative_base) {
+                    return Err(ErrorKind::InfiniteLoop);
+                }
+            }
+        }
+        if let Some(profile) = self.profile.as_mut() {
+            profile.execute(start, op_code);
+        }
+        if let Some(coverage) = self.coverage.as_mut() {
+            coverage.execute(start, op_code, jumped);
+        }
+        self.halted = false;
+        Ok(event)
+    }
+
+    /// Runs the program until it halts, requires an input that is not
+    /// available yet, or produces an output.
+    ///
+    /// Outputs are handed to the caller instead of being stored in `outputs`.
+    pub fn run_until_event(&mut self) -> Result<Event<W>, IntcodeError<W>> {
+        loop {
+            let compiled = self.memory.blocks.is_some()
+                && self.watchpoints.is_empty()
+                && self.history.is_none()
+                && self.smc.is_none()
+                && self.cycles.is_none()
+                && self.profile.is_none()
+                && self.coverage.is_none();
+            let event = if compiled {
+                self.run_block()?
+            } else {
+                self.run_op_code()?
+            };
+            if let Some(event) = event {
+                return Ok(event);
+            }
         }
-        Ok(true)
     }
 
-    pub fn run(&mut self) -> Result<isize, &'static str> {
+    pub fn run(&mut self) -> Result<W, IntcodeError<W>> {
         loop {
-            if !self.run_op_code()? {
-                if self.outputs.len() > 0 {
-                    return Ok(self.outputs[self.outputs.len() - 1]);
-                } else {
-                    return Ok(0);
+            match self.run_until_event()? {
+                Event::Output(value) => self.store_output(value),
+                Event::NeedsInput => return Err(self.error(ErrorKind::OutOfInputs)),
+                Event::Halted => return Ok(self.outputs.last().cloned().unwrap_or_default()),
+            }
+        }
+    }
+}
+
+impl<W: Word, I: Iterator<Item = W>> Computer<I, W> {
+    /// Executes a single instruction, storing its output in `outputs`.
+    pub fn step(&mut self) -> Result<Stop, IntcodeError<W>> {
+        let ip = self.ip;
+        self.watch_hit = None;
+        match self.run_op_code()? {
+            Some(Event::Output(value)) => self.store_output(value),
+            Some(Event::NeedsInput) => return Ok(Stop::NeedsInput),
+            Some(Event::Halted) => return Ok(Stop::Halted),
+            None => (),
+        }
+        Ok(match self.watch_hit.take() {
+            Some((address, access)) => Stop::Watchpoint {
+                ip,
+                address,
+                access,
+            },
+            None => Stop::Step,
+        })
+    }
+
+    /// Runs until a breakpoint or watchpoint is hit, more input is needed or
+    /// the program halts. Outputs are stored in `outputs`.
+    ///
+    /// The instruction at `ip` is always executed, so that a run stopped on a
+    /// breakpoint can be resumed.
+    pub fn run_until_break(&mut self) -> Result<Stop, IntcodeError<W>> {
+        loop {
+            match self.step()? {
+                Stop::Step => {
+                    if self.breakpoints.contains(&self.ip) {
+                        return Ok(Stop::Breakpoint(self.ip));
+                    }
                 }
+                stop => return Ok(stop),
             }
         }
     }
 }
 
+/// Iterating over a `Computer` runs it and yields its outputs as they are
+/// produced, until the program halts. Outputs are not stored in `outputs`.
+///
+/// Iteration ends after yielding an error, including running out of inputs;
+/// `push_input` lets it resume.
+impl<W: Word, I: Iterator<Item = W>> Iterator for Computer<I, W> {
+    type Item = Result<W, IntcodeError<W>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.iteration_failed {
+            return None;
+        }
+        let result = match self.run_until_event() {
+            Ok(Event::Output(value)) => Ok(value),
+            Ok(Event::Halted) => return None,
+            Ok(Event::NeedsInput) => Err(self.error(ErrorKind::OutOfInputs)),
+            Err(error) => Err(error),
+        };
+        self.iteration_failed = result.is_err();
+        Some(result)
+    }
+}
+
+impl<W: Word> Computer<InputQueue<W>, W> {
+    pub fn push_input(&mut self, value: W) {
+        self.inputs.push(value);
+        self.iteration_failed = false;
+    }
+}
+
 #[cfg(test)]
-mod tests {
+pub(crate) mod tests {
     use super::*;
+    use compiler::Engine;
+
+    const ENGINES: [Engine; 2] = [Engine::Interpreter, Engine::Compiled];
 
-    const INPUT: &[isize] = &[
+    /// Day 5 example: outputs 999, 1000 or 1001 when its input is below,
+    /// equal to or above 8
+    pub(crate) const INPUT: &[i64] = &[
         3, 21, 1008, 21, 8, 20, 1005, 20, 22, 107, 8, 21, 20, 1006, 20, 31, 1106, 0, 36, 98, 0, 0,
         1002, 21, 125, 20, 4, 20, 1105, 1, 46, 104, 999, 1105, 1, 46, 1101, 1000, 1, 20, 4, 20,
         1105, 1, 46, 98, 99,
@@ -163,40 +721,281 @@ mod tests {
 
     #[test]
     fn leq8() {
-        let input = INPUT.to_owned();
-        let mut computer = Computer {
-            ip: 0,
-            memory: input,
-            inputs: std::iter::once(7),
-            outputs: Vec::new(),
-        };
-        let value = computer.run().expect("Error while running program");
-        assert_eq!(value, 999);
+        for &engine in &ENGINES {
+            let input = INPUT.to_owned();
+            let mut computer = Computer::new(input, std::iter::once(7));
+            computer.set_engine(engine);
+            let value = computer.run().expect("Error while running program");
+            assert_eq!(value, 999);
+        }
     }
 
     #[test]
     fn eq8() {
-        let input = INPUT.to_owned();
-        let mut computer = Computer {
-            ip: 0,
-            memory: input,
-            inputs: std::iter::once(8),
-            outputs: Vec::new(),
-        };
-        let value = computer.run().expect("Error while running program");
-        assert_eq!(value, 1000);
+        for &engine in &ENGINES {
+            let input = INPUT.to_owned();
+            let mut computer = Computer::new(input, std::iter::once(8));
+            computer.set_engine(engine);
+            let value = computer.run().expect("Error while running program");
+            assert_eq!(value, 1000);
+        }
     }
 
     #[test]
     fn geq8() {
-        let input = INPUT.to_owned();
-        let mut computer = Computer {
-            ip: 0,
-            memory: input,
-            inputs: std::iter::once(9),
-            outputs: Vec::new(),
-        };
+        for &engine in &ENGINES {
+            let input = INPUT.to_owned();
+            let mut computer = Computer::new(input, std::iter::once(9));
+            computer.set_engine(engine);
+            let value = computer.run().expect("Error while running program");
+            assert_eq!(value, 1001);
+        }
+    }
+
+    #[test]
+    fn relative_base() {
+        let quine = [
+            109, 1, 204, -1, 1001, 100, 1, 100, 1008, 100, 16, 101, 1006, 101, 0, 99,
+        ];
+        let mut computer = Computer::new(quine.to_vec(), None.into_iter());
+        computer.run().expect("Error while running program");
+        assert_eq!(computer.outputs, quine.to_vec());
+    }
+
+    #[test]
+    fn large_numbers() {
+        let mut computer = Computer::new(
+            vec![1102, 34915192, 34915192, 7, 4, 7, 99, 0],
+            None.into_iter(),
+        );
+        let value = computer.run().expect("Error while running program");
+        assert_eq!(value, 1219070632396864);
+
+        let mut computer = Computer::new(vec![104, 1125899906842624, 99], None.into_iter());
         let value = computer.run().expect("Error while running program");
-        assert_eq!(value, 1001);
+        assert_eq!(value, 1125899906842624);
+    }
+
+    #[test]
+    fn arithmetic() {
+        // Outputs the sum or product of i64::MAX and rhs, computed at ip 4
+        let program = |opcode, rhs| vec![1101, 1, 1, 11, opcode, i64::MAX, rhs, 11, 4, 11, 99, 0];
+
+        let mut computer = Computer::new(program(1101, 1), None.into_iter());
+        assert_eq!(computer.arithmetic, Arithmetic::Checked);
+        let error = computer.run().unwrap_err();
+        assert_eq!((error.ip, error.kind), (4, ErrorKind::Overflow));
+        assert_eq!(
+            error.to_string(),
+            "arithmetic overflow at ip 4 (instruction 1101, opcode 1)"
+        );
+        let mut computer = Computer::new(program(1102, -1), None.into_iter());
+        assert_eq!(computer.run(), Ok(-i64::MAX));
+
+        let mut computer = Computer::new(program(1101, 1), None.into_iter());
+        computer.arithmetic = Arithmetic::Wrapping;
+        assert_eq!(computer.run(), Ok(i64::MIN));
+        let mut computer = Computer::new(program(1102, 2), None.into_iter());
+        computer.arithmetic = Arithmetic::Wrapping;
+        assert_eq!(computer.run(), Ok(-2));
+
+        let mut computer = Computer::new(program(1101, 1), None.into_iter());
+        computer.arithmetic = Arithmetic::Saturating;
+        assert_eq!(computer.run(), Ok(i64::MAX));
+        let mut computer = Computer::new(program(1102, -2), None.into_iter());
+        computer.arithmetic = Arithmetic::Saturating;
+        assert_eq!(computer.run(), Ok(i64::MIN));
+
+        let program: Vec<i128> = program(1102, 2).into_iter().map(i128::from).collect();
+        let mut computer = Computer::with_memory(program, None.into_iter());
+        assert_eq!(computer.run(), Ok(2 * i128::from(i64::MAX)));
+    }
+
+    #[test]
+    fn run_until_event() {
+        let mut computer = Computer::new(INPUT.to_owned(), InputQueue::new());
+        assert_eq!(computer.run_until_event(), Ok(Event::NeedsInput));
+        assert_eq!(computer.ip, 0);
+        computer.push_input(8);
+        assert_eq!(computer.run_until_event(), Ok(Event::Output(1000)));
+        assert_eq!(computer.run_until_event(), Ok(Event::Halted));
+        assert_eq!(computer.run_until_event(), Ok(Event::Halted));
+        assert!(computer.outputs.is_empty());
+    }
+
+    #[test]
+    fn budget() {
+        for &engine in &ENGINES {
+            let mut computer = Computer::new(INPUT.to_owned(), std::iter::once(8));
+            computer.set_engine(engine);
+            computer.budget = Some(4);
+            let error = computer.run().unwrap_err();
+            assert_eq!((error.ip, error.kind), (26, ErrorKind::BudgetExhausted));
+            assert_eq!(computer.budget, Some(0));
+            // The run resumes with a new budget
+            computer.budget = Some(100);
+            assert_eq!(computer.run(), Ok(1000));
+            assert_eq!(computer.budget, Some(98));
+        }
+
+        let mut computer = Computer::new(vec![1105, 1, 0], None.into_iter());
+        computer.budget = Some(1_000);
+        assert_eq!(computer.run().unwrap_err().kind, ErrorKind::BudgetExhausted);
+    }
+
+    #[test]
+    fn chained_computers() {
+        // Doubles every input it receives, forever
+        let program = vec![3, 11, 1002, 11, 2, 11, 4, 11, 1105, 1, 0];
+        let mut first = Computer::new(program.clone(), InputQueue::new());
+        let mut second = Computer::new(program, InputQueue::new());
+        for value in 1..4 {
+            first.push_input(value);
+            let doubled = match first.run_until_event() {
+                Ok(Event::Output(doubled)) => doubled,
+                other => panic!("Unexpected event: {:?}", other),
+            };
+            second.push_input(doubled);
+            assert_eq!(second.run_until_event(), Ok(Event::Output(4 * value)));
+        }
+        assert_eq!(first.run_until_event(), Ok(Event::NeedsInput));
+        assert_eq!(second.run_until_event(), Ok(Event::NeedsInput));
+    }
+
+    #[test]
+    fn iterator() {
+        // Outputs (x, y, tile) triples
+        let program = vec![104, 1, 104, 2, 104, 3, 104, 6, 104, 5, 104, 4, 99];
+        let mut computer = Computer::new(program.clone(), None.into_iter());
+        let first: Result<Vec<_>, _> = computer.by_ref().take(3).collect();
+        assert_eq!(first, Ok(vec![1, 2, 3]));
+        assert_eq!(computer.next(), Some(Ok(6)));
+        assert_eq!(computer.count(), 2);
+
+        let computer = Computer::new(program, None.into_iter());
+        let outputs: Vec<_> = computer.map(Result::unwrap).collect();
+        let tiles: Vec<_> = outputs
+            .chunks(3)
+            .map(|triple| (triple[0], triple[1], triple[2]))
+            .collect();
+        assert_eq!(tiles, vec![(1, 2, 3), (6, 5, 4)]);
+    }
+
+    #[test]
+    fn iterator_errors() {
+        let mut computer = Computer::new(INPUT.to_owned(), InputQueue::new());
+        let error = computer.next().unwrap().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::OutOfInputs);
+        assert_eq!(computer.next(), None);
+        computer.push_input(9);
+        let outputs: Result<Vec<_>, _> = computer.collect();
+        assert_eq!(outputs, Ok(vec![1001]));
+
+        let mut computer = Computer::new(vec![104, 1, 42], None.into_iter());
+        assert_eq!(computer.next(), Some(Ok(1)));
+        assert_eq!(computer.next().unwrap().unwrap_err().ip, 2);
+        assert_eq!(computer.next(), None);
+    }
+
+    #[test]
+    fn breakpoints() {
+        let mut computer = Computer::new(INPUT.to_owned(), std::iter::once(7));
+        computer.breakpoints.insert(9);
+        computer.breakpoints.insert(31);
+        assert_eq!(computer.run_until_break(), Ok(Stop::Breakpoint(9)));
+        assert_eq!(computer.memory[21], 7);
+        assert_eq!(computer.memory[20], 0);
+        assert_eq!(computer.run_until_break(), Ok(Stop::Breakpoint(31)));
+        assert_eq!(computer.step(), Ok(Stop::Step));
+        assert_eq!(computer.outputs, vec![999]);
+        assert_eq!(computer.run_until_break(), Ok(Stop::Halted));
+    }
+
+    #[test]
+    fn watchpoints() {
+        let mut computer = Computer::new(INPUT.to_owned(), std::iter::once(8));
+        computer.watchpoints.insert(20, Watch::Write);
+        computer.watchpoints.insert(21, Watch::Read);
+        assert_eq!(
+            computer.run_until_break(),
+            Ok(Stop::Watchpoint {
+                ip: 2,
+                address: 21,
+                access: Access::Read
+            })
+        );
+        assert_eq!(computer.ip, 6);
+        computer.watchpoints.remove(&21);
+        assert_eq!(
+            computer.run_until_break(),
+            Ok(Stop::Watchpoint {
+                ip: 22,
+                address: 20,
+                access: Access::Write
+            })
+        );
+        assert_eq!(computer.memory[20], 1000);
+        assert_eq!(computer.run_until_break(), Ok(Stop::Halted));
+        assert_eq!(computer.outputs, vec![1000]);
+    }
+
+    #[test]
+    fn missing_input() {
+        let mut computer = Computer::new(INPUT.to_owned(), None.into_iter());
+        let error = computer.run().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::OutOfInputs);
+        assert_eq!((error.ip, error.instruction, error.opcode), (0, 3, 3));
+    }
+
+    #[test]
+    fn errors() {
+        let mut computer = Computer::new(vec![1101, 1, 1, 5, 42], None.into_iter());
+        let error = computer.run().unwrap_err();
+        assert_eq!(
+            error,
+            IntcodeError {
+                ip: 4,
+                instruction: 42,
+                opcode: 42,
+                kind: ErrorKind::UnknownOpCode,
+            }
+        );
+        assert_eq!(computer.ip, 4);
+        assert_eq!(
+            error.to_string(),
+            "unknown opcode at ip 4 (instruction 42, opcode 42)"
+        );
+
+        let mut computer = Computer::new(vec![1, 0, 0, 0, 1005, 0, -3], None.into_iter());
+        let error = computer.run().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::NegativeAddress(-3));
+        assert_eq!((error.ip, error.instruction, error.opcode), (4, 1005, 5));
+
+        let mut computer = Computer::new(vec![109, -5, 204, 2, 99], None.into_iter());
+        let error = computer.run().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::NegativeAddress(-3));
+        assert_eq!(error.ip, 2);
+
+        let mut computer = Computer::new(vec![1, 0, 0, 0, 1301, 0, 0, 0, 99], None.into_iter());
+        let error = computer.run().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::InvalidMode(3));
+        assert_eq!((error.ip, error.instruction, error.opcode), (4, 1301, 1));
+
+        let mut computer = Computer::new(vec![11101, 1, 1, 0, 99], None.into_iter());
+        let error = computer.run().unwrap_err();
+        assert_eq!(error.kind, ErrorKind::ImmediateWrite);
+        assert_eq!(computer.memory[0], 11101);
+
+        // A failing input instruction leaves the input queued
+        for &(program, kind) in &[
+            ([103, 0, 99], ErrorKind::ImmediateWrite),
+            ([203, -1, 99], ErrorKind::NegativeAddress(-1)),
+        ] {
+            let mut computer = Computer::new(program.to_vec(), InputQueue::new());
+            computer.push_input(5);
+            let error = computer.run().unwrap_err();
+            assert_eq!((error.ip, error.kind), (0, kind));
+            assert_eq!(computer.inputs.len(), 1);
+        }
     }
 }
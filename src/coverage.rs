@@ -0,0 +1,234 @@
+use super::cfg::analyze;
+use super::computer::{Computer, Mode, OpCode};
+use super::disasm::{decode, Instruction};
+use super::word::Word;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+/// Outcomes of a conditional jump.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Branch {
+    pub taken: u64,
+    pub not_taken: u64,
+}
+
+/// Instructions and branches reached by one or more runs of a program.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Coverage {
+    /// Executions of the instruction at each address
+    pub executed: BTreeMap<usize, u64>,
+    /// Outcomes of the conditional jumps executed, by address
+    pub branches: BTreeMap<usize, Branch>,
+}
+
+/// Whether the instruction is a jump whose condition is read from memory,
+/// the others being always or never taken.
+fn conditional(op_code: OpCode) -> bool {
+    match op_code {
+        OpCode::Jnz(modes) | OpCode::Jz(modes) => modes[0] != Mode::Immediate,
+        _ => false,
+    }
+}
+
+fn percent(hit: usize, found: usize) -> f64 {
+    100.0 * hit as f64 / found.max(1) as f64
+}
+
+impl Coverage {
+    pub(crate) fn execute(&mut self, address: usize, op_code: OpCode, jumped: bool) {
+        *self.executed.entry(address).or_insert(0) += 1;
+        if conditional(op_code) {
+            let branch = self.branches.entry(address).or_default();
+            if jumped {
+                branch.taken += 1;
+            } else {
+                branch.not_taken += 1;
+            }
+        }
+    }
+
+    /// Adds the counts of another run.
+    pub fn merge(&mut self, other: &Coverage) {
+        for (&address, &count) in &other.executed {
+            *self.executed.entry(address).or_insert(0) += count;
+        }
+        for (&address, branch) in &other.branches {
+            let merged = self.branches.entry(address).or_default();
+            merged.taken += branch.taken;
+            merged.not_taken += branch.not_taken;
+        }
+    }
+
+    /// Instructions of `memory` reachable from address 0, as found by
+    /// `cfg::analyze`, along with any other executed instruction.
+    fn code(&self, memory: &[i64]) -> Vec<Instruction> {
+        let mut addresses: BTreeSet<_> = analyze(memory)
+            .blocks
+            .values()
+            .flat_map(|block| {
+                block
+                    .instructions
+                    .iter()
+                    .map(|instruction| instruction.address)
+            })
+            .collect();
+        addresses.extend(self.executed.keys());
+        addresses
+            .into_iter()
+            .filter_map(|address| decode(memory, address))
+            .collect()
+    }
+
+    /// Share of the instructions of `memory` executed, and of the outcomes
+    /// of its conditional jumps seen, as in
+    /// `8/15 instructions (53.3%), 2/4 branches (50.0%)`.
+    pub fn summary(&self, memory: &[i64]) -> String {
+        let code = self.code(memory);
+        let hit = code
+            .iter()
+            .filter(|instruction| self.executed.contains_key(&instruction.address))
+            .count();
+        let mut branches = 0;
+        let mut branches_hit = 0;
+        for instruction in code
+            .iter()
+            .filter(|instruction| conditional(instruction.opcode))
+        {
+            branches += 2;
+            if let Some(branch) = self.branches.get(&instruction.address) {
+                branches_hit += (branch.taken > 0) as usize + (branch.not_taken > 0) as usize;
+            }
+        }
+        format!(
+            "{}/{} instructions ({:.1}%), {}/{} branches ({:.1}%)",
+            hit,
+            code.len(),
+            percent(hit, code.len()),
+            branches_hit,
+            branches,
+            percent(branches_hit, branches)
+        )
+    }
+
+    /// Listing of `memory` with the execution count of every instruction,
+    /// `#####` for those never executed, and the outcomes of conditional
+    /// jumps. Cells that are not code are printed as `DATA`.
+    pub fn annotate(&self, memory: &[i64]) -> String {
+        let code: BTreeMap<_, _> = self
+            .code(memory)
+            .into_iter()
+            .map(|instruction| (instruction.address, instruction))
+            .collect();
+        let mut listing = String::new();
+        let mut address = 0;
+        while address < memory.len() {
+            let instruction = match code.get(&address) {
+                Some(instruction) => instruction,
+                None => {
+                    writeln!(
+                        listing,
+                        "{:>8} {:>6}: DATA {}",
+                        "", address, memory[address]
+                    )
+                    .unwrap();
+                    address += 1;
+                    continue;
+                }
+            };
+            let count = match self.executed.get(&address) {
+                Some(count) => count.to_string(),
+                None => "#####".to_owned(),
+            };
+            write!(listing, "{:>8} {:>6}: {}", count, address, instruction).unwrap();
+            if conditional(instruction.opcode) {
+                let branch = self.branches.get(&address).cloned().unwrap_or_default();
+                write!(
+                    listing,
+                    " ; taken {}, not taken {}",
+                    branch.taken, branch.not_taken
+                )
+                .unwrap();
+            }
+            writeln!(listing).unwrap();
+            address += instruction.size();
+        }
+        listing
+    }
+}
+
+impl<W: Word, I: Iterator<Item = W>> Computer<I, W> {
+    /// Starts recording the executed instructions and the outcomes of
+    /// conditional jumps, from scratch.
+    pub fn enable_coverage(&mut self) {
+        self.coverage = Some(Coverage::default());
+    }
+
+    pub fn disable_coverage(&mut self) {
+        self.coverage = None;
+    }
+
+    /// Coverage recorded since it was enabled; `Coverage::merge` gathers
+    /// the results of several computers.
+    pub fn coverage(&self) -> Option<&Coverage> {
+        self.coverage.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use compiler::Engine;
+    use computer::tests::INPUT;
+
+    fn run(input: i64) -> Coverage {
+        let mut computer = Computer::new(INPUT.to_owned(), std::iter::once(input));
+        // Coverage falls back to the interpreter
+        computer.set_engine(Engine::Compiled);
+        computer.enable_coverage();
+        computer.run().expect("Error while running program");
+        // Polling the halted machine executes nothing more
+        computer.run().expect("Error while running program");
+        computer.coverage().unwrap().clone()
+    }
+
+    #[test]
+    fn single_run() {
+        let coverage = run(7);
+        assert_eq!(
+            coverage.summary(INPUT),
+            "8/15 instructions (53.3%), 2/4 branches (50.0%)"
+        );
+        assert_eq!(
+            coverage.branches[&13],
+            Branch {
+                taken: 1,
+                not_taken: 0
+            }
+        );
+        let listing = coverage.annotate(INPUT);
+        assert!(listing.contains("\n       1      6: JNZ [20], #22 ; taken 0, not taken 1\n"));
+        assert!(listing.contains("\n   #####     22: MUL [21], #125, [20]\n"));
+        assert!(listing.contains("\n       1     31: OUT #999\n"));
+        assert!(listing.contains("\n             19: DATA 98\n"));
+    }
+
+    #[test]
+    fn merged_runs() {
+        let mut coverage = run(7);
+        coverage.merge(&run(8));
+        assert_eq!(
+            coverage.summary(INPUT),
+            "11/15 instructions (73.3%), 3/4 branches (75.0%)"
+        );
+        coverage.merge(&run(9));
+        coverage.merge(&run(9));
+        assert_eq!(
+            coverage.summary(INPUT),
+            "15/15 instructions (100.0%), 4/4 branches (100.0%)"
+        );
+        assert_eq!(coverage.executed[&0], 4);
+        assert_eq!(coverage.executed[&36], 2);
+        assert_eq!(coverage.executed[&46], 4);
+        assert!(!coverage.annotate(INPUT).contains("#####"));
+    }
+}
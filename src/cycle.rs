@@ -0,0 +1,174 @@
+use super::computer::Computer;
+use super::word::Word;
+use std::collections::HashMap;
+
+/// Looks for a state reached twice at backward jumps, with no input read in
+/// between: execution being deterministic, the program then loops forever.
+///
+/// The state at a backward jump is kept as a checkpoint, replaced after 1,
+/// 2, 4... more jumps (Brent's algorithm), so any loop is found within a few
+/// times its length. Instead of a copy of the memory, the checkpoint keeps
+/// the previous value of the cells written since and counts those that
+/// differ, so comparing states takes constant time.
+#[derive(Clone, Debug)]
+pub(crate) struct CycleDetector<W> {
+    /// `ip` and relative base at the checkpoint
+    checkpoint: Option<(usize, i64)>,
+    /// Value at the checkpoint of every cell written since
+    original: HashMap<usize, W>,
+    /// Number of cells that no longer hold their value at the checkpoint
+    changed: usize,
+    /// Backward jumps since the checkpoint
+    jumps: u64,
+    /// Backward jumps until the checkpoint is replaced
+    period: u64,
+}
+
+impl<W: Word> CycleDetector<W> {
+    pub(crate) fn new() -> Self {
+        CycleDetector {
+            checkpoint: None,
+            original: HashMap::new(),
+            changed: 0,
+            jumps: 0,
+            period: 1,
+        }
+    }
+
+    /// Forgets the checkpoint, as reading an input makes it unreliable.
+    pub(crate) fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Records a write of `new` over `old` at `address`.
+    pub(crate) fn write(&mut self, address: usize, old: &W, new: &W) {
+        if self.checkpoint.is_none() {
+            return;
+        }
+        let original = self.original.entry(address).or_insert_with(|| old.clone());
+        match (original != old, original != new) {
+            (false, true) => self.changed += 1,
+            (true, false) => self.changed -= 1,
+            _ => (),
+        }
+    }
+
+    /// Records the state right after a backward jump, returning whether it
+    /// is the one of the checkpoint.
+    pub(crate) fn repeated(&mut self, ip: usize, relative_base: i64) -> bool {
+        if self.checkpoint == Some((ip, relative_base)) && self.changed == 0 {
+            return true;
+        }
+        self.jumps += 1;
+        if self.checkpoint.is_none() || self.jumps == self.period {
+            self.checkpoint = Some((ip, relative_base));
+            self.original.clear();
+            self.changed = 0;
+            self.jumps = 0;
+            self.period *= 2;
+        }
+        false
+    }
+}
+
+impl<W: Word, I: Iterator<Item = W>> Computer<I, W> {
+    /// Makes the program fail with `ErrorKind::InfiniteLoop` once it is
+    /// proven to loop forever, including loops that keep producing outputs.
+    ///
+    /// Changing the state from outside between runs can fool the detection:
+    /// enable it again to start over.
+    pub fn enable_loop_detection(&mut self) {
+        self.cycles = Some(CycleDetector::new());
+    }
+
+    pub fn disable_loop_detection(&mut self) {
+        self.cycles = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use asm::assemble;
+    use computer::{Computer, ErrorKind};
+
+    fn run(source: &str, inputs: &[i64]) -> Result<i64, ErrorKind> {
+        let program = assemble(source).unwrap();
+        let mut computer = Computer::new(program, inputs.iter().cloned());
+        computer.enable_loop_detection();
+        computer.run().map_err(|error| error.kind)
+    }
+
+    #[test]
+    fn loops() {
+        let error = {
+            let mut computer =
+                Computer::new(assemble("loop: JZ #0, #loop").unwrap(), None.into_iter());
+            computer.enable_loop_detection();
+            computer.run().unwrap_err()
+        };
+        assert_eq!((error.ip, error.kind), (0, ErrorKind::InfiniteLoop));
+
+        // Flips x forever
+        let flip = "
+loop: EQ [x], #0, [x]
+      OUT [x]
+      JZ #0, #loop
+x:    DATA 0
+";
+        assert_eq!(run(flip, &[]), Err(ErrorKind::InfiniteLoop));
+
+        // Counts down to 0, through the same ip and relative base every time
+        let countdown = "
+loop: ADD [n], #-1, [n]
+      JNZ [n], #loop
+      OUT #7
+      HLT
+n:    DATA 1000
+";
+        assert_eq!(run(countdown, &[]), Ok(7));
+
+        // A long countdown over a large memory, then a cell flipped forever
+        let mut program = assemble(
+            "
+loop: EQ [x], #0, [x]
+      EQ [x], #0, [x]
+      ADD [n], #-1, [n]
+      JNZ [n], #loop
+spin: EQ [x], #0, [x]
+      JZ #0, #spin
+x:    DATA 0
+n:    DATA 100000
+",
+        )
+        .unwrap();
+        program.resize(1_000_000, 0);
+        let mut computer = Computer::new(program, None.into_iter());
+        computer.enable_loop_detection();
+        let error = computer.run().unwrap_err();
+        assert_eq!((error.ip, error.kind), (19, ErrorKind::InfiniteLoop));
+    }
+
+    #[test]
+    fn inputs() {
+        // Echoes its inputs until a 0
+        let echo = "
+loop: IN [x]
+      OUT [x]
+      JNZ [x], #loop
+      HLT
+x:    DATA 0
+";
+        assert_eq!(run(echo, &[5, 5, 5, 5, 0]), Ok(0));
+
+        // Never proven to loop, as every input could be the last
+        let program = assemble(echo).unwrap();
+        let mut computer = Computer::new(program, std::iter::repeat(5));
+        computer.enable_loop_detection();
+        computer.budget = Some(1000);
+        assert_eq!(
+            computer.run().map_err(|error| error.kind),
+            Err(ErrorKind::BudgetExhausted)
+        );
+        assert_eq!(computer.outputs.len(), 333);
+    }
+}
@@ -0,0 +1,118 @@
+use super::computer::{ErrorKind, OpCode};
+use super::word::Word;
+
+/// Decoded instructions of the contiguous part of a `Memory`, by address.
+///
+/// Only the instruction word is decoded, parameters are still read from
+/// memory, so an entry has to be dropped only when its own address is
+/// written to.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct DecodeCache {
+    entries: Vec<Option<OpCode>>,
+}
+
+impl DecodeCache {
+    /// Decodes every word of `cells` that is a valid instruction.
+    pub(crate) fn new<W: Word>(cells: &[W]) -> Self {
+        DecodeCache {
+            entries: cells
+                .iter()
+                .map(|word| OpCode::from_word(word).ok())
+                .collect(),
+        }
+    }
+
+    /// Decodes `word`, found at `address`, unless it already was.
+    pub(crate) fn decode<W: Word>(
+        &mut self,
+        address: usize,
+        word: &W,
+    ) -> Result<OpCode, ErrorKind> {
+        if let Some(Some(op_code)) = self.entries.get(address) {
+            return Ok(*op_code);
+        }
+        let op_code = OpCode::from_word(word)?;
+        if let Some(entry) = self.entries.get_mut(address) {
+            *entry = Some(op_code);
+        }
+        Ok(op_code)
+    }
+
+    pub(crate) fn invalidate(&mut self, address: usize) {
+        if let Some(entry) = self.entries.get_mut(address) {
+            *entry = None;
+        }
+    }
+
+    /// Makes room for the contiguous part of the memory, grown to `len`.
+    pub(crate) fn grow(&mut self, len: usize) {
+        if len > self.entries.len() {
+            self.entries.resize(len, None);
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use asm::assemble;
+    use computer::tests::INPUT;
+    use computer::{Computer, InputQueue};
+
+    /// Patches its first instruction from an addition to a multiplication
+    /// after running it once.
+    pub(crate) const PATCH: &str = "
+start:   ADD #2, #3, [result]
+         OUT [result]
+         JNZ [patched], #end
+         ADD #1, #0, [patched]
+         ADD #1102, #0, [start]
+         JZ #0, #start
+end:     HLT
+result:  DATA 0
+patched: DATA 0
+";
+
+    fn run(program: &[i64], inputs: &[i64], cached: bool) -> Vec<i64> {
+        let mut computer = Computer::new(program.to_vec(), inputs.iter().cloned());
+        if cached {
+            computer.memory.enable_decode_cache();
+        }
+        computer.run().expect("Error while running program");
+        computer.outputs
+    }
+
+    #[test]
+    fn same_results() {
+        let patch = assemble(PATCH).unwrap();
+        assert_eq!(run(&patch, &[], false), vec![5, 6]);
+        assert_eq!(run(&patch, &[], true), vec![5, 6]);
+        for input in 6..11 {
+            assert_eq!(run(INPUT, &[input], true), run(INPUT, &[input], false));
+        }
+
+        // Day 2 example, overwriting the instruction at 4 before reaching it
+        let mut computer = Computer::new(vec![1, 1, 1, 4, 99, 5, 6, 0, 99], None.into_iter());
+        computer.memory.enable_decode_cache();
+        computer.run().expect("Error while running program");
+        assert_eq!(computer.memory.to_vec(), vec![30, 1, 1, 4, 2, 5, 6, 0, 99]);
+    }
+
+    #[test]
+    fn external_writes() {
+        let patch = assemble(PATCH).unwrap();
+        let mut computer = Computer::new(patch, InputQueue::new());
+        computer.memory.enable_decode_cache();
+        computer.enable_history(100);
+        assert_eq!(computer.run(), Ok(6));
+
+        // Undoing the patch must bring the addition back
+        while computer.step_back() {}
+        assert_eq!(computer.run(), Ok(6));
+        assert_eq!(computer.outputs, vec![5, 6]);
+
+        while computer.step_back() {}
+        computer.memory[0] = 1102;
+        computer.run().expect("Error while running program");
+        assert_eq!(computer.outputs, vec![6, 6]);
+    }
+}
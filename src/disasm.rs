@@ -0,0 +1,126 @@
+use super::computer::{Mode, OpCode};
+use std::fmt;
+use std::fmt::Write;
+
+/// An instruction decoded from memory, with its raw parameters.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Instruction {
+    pub address: usize,
+    pub opcode: OpCode,
+    pub params: Vec<i64>,
+}
+
+impl Instruction {
+    /// Number of memory cells taken by the instruction.
+    pub fn size(&self) -> usize {
+        self.params.len() + 1
+    }
+}
+
+/// Formats a parameter the way its mode makes the machine use it.
+pub fn format_param(mode: Mode, value: i64) -> String {
+    match mode {
+        Mode::Position => format!("[{}]", value),
+        Mode::Immediate => format!("#{}", value),
+        Mode::Relative if value < 0 => format!("rb{}", value),
+        Mode::Relative => format!("rb+{}", value),
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.opcode)?;
+        for (i, (mode, value)) in self.opcode.modes().iter().zip(&self.params).enumerate() {
+            let separator = if i == 0 { " " } else { ", " };
+            write!(f, "{}{}", separator, format_param(*mode, *value))?;
+        }
+        Ok(())
+    }
+}
+
+/// Decodes the instruction at `address`, if the word there is a valid
+/// instruction whose parameters all fit in memory. Instructions writing to
+/// an immediate parameter are rejected, as the machine would.
+pub fn decode(memory: &[i64], address: usize) -> Option<Instruction> {
+    let opcode = OpCode::from_word(memory.get(address)?).ok()?;
+    if let Some(param) = opcode.written_param() {
+        if opcode.modes()[param] == Mode::Immediate {
+            return None;
+        }
+    }
+    let params = memory.get(address + 1..address + 1 + opcode.modes().len())?;
+    Some(Instruction {
+        address,
+        opcode,
+        params: params.to_vec(),
+    })
+}
+
+/// Produces a listing of the program, one instruction per line.
+///
+/// Memory is decoded linearly from address 0; words that are not valid
+/// instructions are printed as `DATA`.
+pub fn disassemble(memory: &[i64]) -> String {
+    let mut listing = String::new();
+    let mut address = 0;
+    while address < memory.len() {
+        match decode(memory, address) {
+            Some(instruction) => {
+                writeln!(listing, "{:>6}: {}", address, instruction).unwrap();
+                address += instruction.size();
+            }
+            None => {
+                writeln!(listing, "{:>6}: DATA {}", address, memory[address]).unwrap();
+                address += 1;
+            }
+        }
+    }
+    listing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asm::assemble;
+
+    #[test]
+    fn instructions() {
+        let listing = disassemble(&[1, 9, 10, 3, 2, 3, 11, 0, 99, 30, 40, 50]);
+        assert_eq!(
+            listing,
+            "     0: ADD [9], [10], [3]
+     4: MUL [3], [11], [0]
+     8: HLT
+     9: DATA 30
+    10: DATA 40
+    11: DATA 50
+"
+        );
+    }
+
+    #[test]
+    fn modes() {
+        let listing = disassemble(&[109, 1, 204, -1, 21101, 5, 7, 3, 1005, 0]);
+        assert_eq!(
+            listing,
+            "     0: ARB #1
+     2: OUT rb-1
+     4: ADD #5, #7, rb+3
+     8: DATA 1005
+     9: DATA 0
+"
+        );
+    }
+
+    #[test]
+    fn immediate_writes() {
+        let listing = disassemble(&[11101, 1, 1, 0, 99]);
+        assert_eq!(
+            listing,
+            "     0: DATA 11101
+     1: ADD [1], [0], [99]
+"
+        );
+        assert_eq!(assemble(&listing), Ok(vec![11101, 1, 1, 0, 99]));
+    }
+}
@@ -0,0 +1,181 @@
+use super::computer::{Computer, InputQueue};
+use super::word::Word;
+use std::collections::VecDeque;
+
+/// What is needed to undo one executed instruction.
+#[derive(Clone, Debug)]
+pub(crate) struct Undo<W> {
+    pub(crate) ip: usize,
+    pub(crate) relative_base: i64,
+    /// Written address and its previous value
+    pub(crate) write: Option<(usize, W)>,
+    pub(crate) input: Option<W>,
+    /// Whether an output was stored in `Computer::outputs`
+    pub(crate) output: bool,
+}
+
+/// Undo log of the last executed instructions.
+#[derive(Clone, Debug)]
+pub(crate) struct History<W> {
+    entries: VecDeque<Undo<W>>,
+    capacity: usize,
+}
+
+impl<W> History<W> {
+    /// Starts recording the instruction about to be executed.
+    pub(crate) fn begin(&mut self, ip: usize, relative_base: i64) {
+        self.entries.push_back(Undo {
+            ip,
+            relative_base,
+            write: None,
+            input: None,
+            output: false,
+        });
+    }
+
+    /// Entry of the instruction being (or last) executed.
+    pub(crate) fn current(&mut self) -> &mut Undo<W> {
+        self.entries
+            .back_mut()
+            .expect("No instruction is being recorded")
+    }
+
+    /// Keeps the entry of an executed instruction, dropping the oldest one
+    /// if the log is full.
+    pub(crate) fn commit(&mut self) {
+        if self.entries.len() > self.capacity {
+            self.entries.pop_front();
+        }
+    }
+
+    /// Drops the entry of an instruction that was not executed after all.
+    pub(crate) fn cancel(&mut self) {
+        self.entries.pop_back();
+    }
+}
+
+impl<W: Word, I: Iterator<Item = W>> Computer<I, W> {
+    /// Starts recording the last `capacity` executed instructions, so that
+    /// they can be undone with `step_back`.
+    pub fn enable_history(&mut self, capacity: usize) {
+        self.history = Some(History {
+            entries: VecDeque::new(),
+            capacity: capacity.max(1),
+        });
+    }
+
+    pub fn disable_history(&mut self) {
+        self.history = None;
+    }
+
+    /// Number of instructions that can be undone.
+    pub fn history_len(&self) -> usize {
+        self.history
+            .as_ref()
+            .map_or(0, |history| history.entries.len())
+    }
+}
+
+impl<W: Word> Computer<InputQueue<W>, W> {
+    /// Undoes the last recorded instruction: restores `ip`, the relative base
+    /// and the written memory cell, puts back the consumed input and removes
+    /// the produced output from `outputs`.
+    ///
+    /// Outputs returned by `run_until_event` cannot be taken back. Returns
+    /// false if there is nothing to undo.
+    pub fn step_back(&mut self) -> bool {
+        let undo = match self.history.as_mut().and_then(|h| h.entries.pop_back()) {
+            Some(undo) => undo,
+            None => return false,
+        };
+        self.ip = undo.ip;
+        self.relative_base = undo.relative_base;
+        if let Some((address, value)) = undo.write {
+            self.memory[address] = value;
+        }
+        if let Some(input) = undo.input {
+            self.inputs.unread(input);
+        }
+        if undo.output {
+            self.outputs.pop();
+        }
+        true
+    }
+
+    /// Steps back until `ip` reaches `address`, i.e. to just before the last
+    /// execution of the instruction there. If it is not in the recorded
+    /// history, the whole history is undone and false is returned.
+    pub fn run_back_to(&mut self, address: usize) -> bool {
+        while self.step_back() {
+            if self.ip == address {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use computer::tests::INPUT;
+    use computer::{Event, Stop};
+
+    #[test]
+    fn step_back() {
+        let mut computer = Computer::new(INPUT.to_owned(), InputQueue::new());
+        computer.enable_history(100);
+        computer.push_input(9);
+        assert_eq!(computer.run(), Ok(1001));
+        assert_eq!(computer.history_len(), 9);
+
+        assert!(computer.step_back());
+        assert_eq!(computer.ip, 42);
+        assert_eq!(computer.outputs, vec![1001]);
+        assert!(computer.step_back());
+        assert_eq!(computer.ip, 40);
+        assert!(computer.outputs.is_empty());
+        assert!(computer.run_back_to(36));
+        assert_eq!(computer.memory[20], 1);
+
+        while computer.step_back() {}
+        assert_eq!(computer.ip, 0);
+        assert_eq!(computer.memory.to_vec(), INPUT.to_owned());
+        assert_eq!(computer.inputs.iter().collect::<Vec<_>>(), vec![&9]);
+
+        assert_eq!(computer.run(), Ok(1001));
+        assert!(!computer.run_back_to(19));
+        assert_eq!(computer.ip, 0);
+    }
+
+    #[test]
+    fn capacity() {
+        let mut computer = Computer::new(INPUT.to_owned(), InputQueue::new());
+        computer.enable_history(2);
+        computer.push_input(7);
+        assert_eq!(computer.step(), Ok(Stop::Step));
+        assert_eq!(computer.step(), Ok(Stop::Step));
+        assert_eq!(computer.step(), Ok(Stop::Step));
+        assert_eq!(computer.run_until_event(), Ok(Event::Output(999)));
+        assert_eq!(computer.history_len(), 2);
+        assert!(computer.step_back());
+        assert!(computer.step_back());
+        assert!(!computer.step_back());
+        assert_eq!(computer.ip, 13);
+
+        // Waiting for input or halting does not push recorded instructions out
+        let mut computer = Computer::new(INPUT.to_owned(), InputQueue::new());
+        computer.enable_history(2);
+        computer.push_input(8);
+        assert_eq!(computer.step(), Ok(Stop::Step));
+        assert_eq!(computer.step(), Ok(Stop::Step));
+        assert_eq!(computer.history_len(), 2);
+        computer.ip = 0;
+        assert_eq!(computer.step(), Ok(Stop::NeedsInput));
+        assert_eq!(computer.history_len(), 2);
+        computer.ip = 46;
+        assert_eq!(computer.run(), Ok(0));
+        assert_eq!(computer.run(), Ok(0));
+        assert_eq!(computer.history_len(), 2);
+    }
+}
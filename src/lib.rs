@@ -2,15 +2,34 @@
 #![feature(const_generics)]
 
 extern crate aoc_runner;
+extern crate futures;
+extern crate num_bigint;
 extern crate rayon;
 #[macro_use]
 extern crate aoc_runner_derive;
 
+pub mod amplifier;
+pub mod asm;
+pub mod async_computer;
+pub mod cfg;
+pub mod channel;
+pub mod compiler;
 pub mod computer;
+pub mod coverage;
+pub mod cycle;
 pub mod day01;
 pub mod day02;
 pub mod day03;
 pub mod day04;
 pub mod day05;
+mod decode_cache;
+pub mod disasm;
+pub mod history;
+pub mod memory;
+pub mod network;
+pub mod profile;
+pub mod smc;
+pub mod snapshot;
+pub mod word;
 
 aoc_lib! { year = 2019 }
@@ -0,0 +1,230 @@
+use super::compiler::{Block, BlockCache};
+use super::computer::{ErrorKind, OpCode};
+use super::decode_cache::DecodeCache;
+use super::word::Word;
+use std::collections::HashMap;
+use std::ops::{Index, IndexMut};
+use std::sync::Arc;
+
+const PAGE_BITS: usize = 10;
+const PAGE_SIZE: usize = 1 << PAGE_BITS;
+
+/// `PAGE_SIZE` cells
+type Page<W> = Box<[W]>;
+
+/// Intcode memory: reads past the end return 0 and writes grow the memory.
+///
+/// The loaded program and anything written close to its end live in a
+/// contiguous vector, while far-away writes land in lazily allocated pages.
+#[derive(Clone, Debug, Default)]
+pub struct Memory<W = i64> {
+    dense: Vec<W>,
+    pages: HashMap<usize, Page<W>>,
+    /// One past the highest address written, or the length of the loaded
+    /// program if greater
+    end: usize,
+    /// Value of the unallocated cells
+    zero: W,
+    cache: Option<DecodeCache>,
+    /// Set when using the compiled engine
+    pub(crate) blocks: Option<BlockCache<W>>,
+}
+
+impl<W: Word> Memory<W> {
+    pub fn get(&self, address: usize) -> W {
+        self[address].clone()
+    }
+
+    pub fn set(&mut self, address: usize, value: W) {
+        self[address] = value;
+    }
+
+    /// Length of the memory image: one past the highest address written,
+    /// or the length of the loaded program if greater.
+    pub fn len(&self) -> usize {
+        self.end
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Copies the whole memory image, unallocated cells included as 0.
+    pub fn to_vec(&self) -> Vec<W> {
+        let mut image = Vec::with_capacity(self.end);
+        for (start, cells) in self.segments() {
+            image.resize(start, W::default());
+            image.extend_from_slice(cells);
+        }
+        image.resize(self.end, W::default());
+        image
+    }
+
+    /// Allocated memory segments, as their start address and content, up
+    /// to the end of the memory image. The first segment always starts at
+    /// address 0.
+    pub fn segments(&self) -> Vec<(usize, &[W])> {
+        let mut segments = vec![(0, &self.dense[..self.dense.len().min(self.end)])];
+        let mut pages: Vec<_> = self.pages.iter().collect();
+        pages.sort_by_key(|(page, _)| **page);
+        segments.extend(pages.into_iter().map(|(page, cells)| {
+            let start = page << PAGE_BITS;
+            (start, &cells[..PAGE_SIZE.min(self.end - start)])
+        }));
+        segments
+    }
+
+    /// Decodes the instructions of the contiguous part once and for all:
+    /// only those written to are decoded again.
+    ///
+    /// Decoding everything up front pays off for long runs, or when the
+    /// memory is cloned for many runs of the same program.
+    pub fn enable_decode_cache(&mut self) {
+        self.cache = Some(DecodeCache::new(&self.dense));
+    }
+
+    pub fn disable_decode_cache(&mut self) {
+        self.cache = None;
+    }
+
+    /// Decodes the instruction at `address`.
+    pub(crate) fn decode(&mut self, address: usize) -> Result<OpCode, ErrorKind> {
+        match self.cache.as_mut() {
+            Some(cache) if address < self.dense.len() => {
+                cache.decode(address, &self.dense[address])
+            }
+            _ => OpCode::from_word(&self[address]),
+        }
+    }
+
+    /// Compiled block starting at `address`, if the compiled engine is used
+    /// and the address is in the contiguous part.
+    pub(crate) fn block(&mut self, address: usize) -> Option<Arc<Block<W>>> {
+        if address >= self.dense.len() {
+            return None;
+        }
+        let mut blocks = self.blocks.take()?;
+        let block = blocks.get(self, address, self.dense.len());
+        self.blocks = Some(blocks);
+        Some(block)
+    }
+
+    fn grow_dense(&mut self, address: usize) {
+        let new_len = (address / PAGE_SIZE + 1) * PAGE_SIZE;
+        let first_page = self.dense.len() / PAGE_SIZE;
+        self.dense.resize(new_len, W::default());
+        if let Some(cache) = self.cache.as_mut() {
+            cache.grow(new_len);
+        }
+        for page in first_page..new_len / PAGE_SIZE {
+            if let Some(cells) = self.pages.remove(&page) {
+                let start = page << PAGE_BITS;
+                self.dense[start..start + PAGE_SIZE].clone_from_slice(&cells[..]);
+            }
+        }
+    }
+}
+
+impl<W: Word> From<Vec<W>> for Memory<W> {
+    fn from(dense: Vec<W>) -> Self {
+        Memory {
+            end: dense.len(),
+            dense,
+            pages: HashMap::new(),
+            zero: W::default(),
+            cache: None,
+            blocks: None,
+        }
+    }
+}
+
+impl<W: Word> Index<usize> for Memory<W> {
+    type Output = W;
+
+    fn index(&self, address: usize) -> &W {
+        if address < self.dense.len() {
+            &self.dense[address]
+        } else {
+            match self.pages.get(&(address >> PAGE_BITS)) {
+                Some(cells) => &cells[address % PAGE_SIZE],
+                None => &self.zero,
+            }
+        }
+    }
+}
+
+impl<W: Word> IndexMut<usize> for Memory<W> {
+    fn index_mut(&mut self, address: usize) -> &mut W {
+        self.end = self.end.max(address.saturating_add(1));
+        if address >= self.dense.len() {
+            if address < self.dense.len() + PAGE_SIZE {
+                self.grow_dense(address);
+            } else {
+                return &mut self
+                    .pages
+                    .entry(address >> PAGE_BITS)
+                    .or_insert_with(|| vec![W::default(); PAGE_SIZE].into_boxed_slice())
+                    [address % PAGE_SIZE];
+            }
+        }
+        if let Some(cache) = self.cache.as_mut() {
+            cache.invalidate(address);
+        }
+        if let Some(blocks) = self.blocks.as_mut() {
+            blocks.invalidate(address);
+        }
+        &mut self.dense[address]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_past_end() {
+        let memory: Memory = Memory::from(vec![1, 2, 3]);
+        assert_eq!(memory[2], 3);
+        assert_eq!(memory[3], 0);
+        assert_eq!(memory[1_000_000_000], 0);
+        assert_eq!(memory.to_vec(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn grows_on_write() {
+        let mut memory: Memory = Memory::from(vec![1, 2, 3]);
+        memory[10] = 4;
+        assert_eq!(memory[10], 4);
+        assert_eq!(memory.len(), 11);
+        assert_eq!(memory.to_vec(), vec![1, 2, 3, 0, 0, 0, 0, 0, 0, 0, 4]);
+
+        // Writing the end of the image again does not change its length
+        memory[10] = 0;
+        memory[3] = 5;
+        assert_eq!(memory.len(), 11);
+    }
+
+    #[test]
+    fn sparse_writes() {
+        let mut memory: Memory = Memory::from(vec![1, 2, 3]);
+        memory[1_000_000_000] = 5;
+        memory[5 * PAGE_SIZE / 2] = 6;
+        assert_eq!(memory.pages.len(), 2);
+        assert_eq!(memory[1_000_000_000], 5);
+
+        // Growing the contiguous part over an allocated page keeps its content
+        memory[PAGE_SIZE + 1] = 7;
+        memory[2 * PAGE_SIZE + 1] = 8;
+        assert_eq!(memory.pages.len(), 1);
+        assert_eq!(memory[5 * PAGE_SIZE / 2], 6);
+        assert_eq!(memory[PAGE_SIZE + 1], 7);
+        assert_eq!(memory[1_000_000_000], 5);
+
+        let segments = memory.segments();
+        assert_eq!(segments.len(), 2);
+        assert_eq!((segments[0].0, segments[0].1.len()), (0, 3 * PAGE_SIZE));
+        assert_eq!(segments[1].0, 1_000_000_000 / PAGE_SIZE * PAGE_SIZE);
+        assert_eq!(memory.len(), 1_000_000_001);
+        assert_eq!(segments[1].0 + segments[1].1.len(), memory.len());
+    }
+}
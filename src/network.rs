@@ -0,0 +1,264 @@
+use super::computer::{Computer, Event, InputQueue, IntcodeError};
+
+/// Input given to a machine that tries to read while its queue is empty.
+const NO_PACKET: i64 = -1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Packet {
+    pub dest: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+/// Configuration of the NAT: a device listening on `address` that keeps the
+/// last packet it received, and sends it to machine `target` once the
+/// network has been idle for `idle_rounds` rounds.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nat {
+    pub address: i64,
+    pub target: usize,
+    pub idle_rounds: usize,
+}
+
+impl Default for Nat {
+    fn default() -> Self {
+        Nat {
+            address: 255,
+            target: 0,
+            idle_rounds: 2,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NetEvent {
+    /// A packet was sent to an address that is neither a machine nor the NAT
+    Unroutable(Packet),
+    NatReceived(Packet),
+    /// The NAT woke the network up by sending this packet
+    NatSent(Packet),
+    /// The network is idle and nothing can wake it up
+    Idle,
+}
+
+/// Machines exchanging `(dest, x, y)` packets through their outputs.
+///
+/// Machines are run in rounds: each one runs until it has to wait for a
+/// packet. A machine reading from an empty queue gets -1 instead.
+pub struct Network {
+    pub machines: Vec<Computer<InputQueue>>,
+    pub nat: Option<Nat>,
+    /// Outputs of each machine that do not form a full packet yet
+    partial: Vec<Vec<i64>>,
+    nat_packet: Option<Packet>,
+    idle_rounds: usize,
+}
+
+impl Network {
+    /// Boots `size` copies of the program, each receiving its address as
+    /// first input.
+    pub fn new(program: &[i64], size: usize, nat: Option<Nat>) -> Self {
+        let machines = (0..size)
+            .map(|address| {
+                let mut computer = Computer::new(program.to_vec(), InputQueue::new());
+                computer.push_input(address as i64);
+                computer
+            })
+            .collect();
+        Network {
+            machines,
+            nat,
+            partial: vec![Vec::new(); size],
+            nat_packet: None,
+            idle_rounds: 0,
+        }
+    }
+
+    fn route(&mut self, packet: Packet) -> Option<NetEvent> {
+        if packet.dest >= 0 && (packet.dest as usize) < self.machines.len() {
+            let machine = &mut self.machines[packet.dest as usize];
+            machine.push_input(packet.x);
+            machine.push_input(packet.y);
+            None
+        } else if self.nat.map(|nat| nat.address) == Some(packet.dest) {
+            self.nat_packet = Some(packet);
+            Some(NetEvent::NatReceived(packet))
+        } else {
+            Some(NetEvent::Unroutable(packet))
+        }
+    }
+
+    /// Runs every machine once until it waits for a packet, and returns
+    /// what happened on the network.
+    pub fn round(&mut self) -> Result<Vec<NetEvent>, IntcodeError> {
+        let mut events = Vec::new();
+        let mut active = false;
+        for i in 0..self.machines.len() {
+            // Packets sent to a halted machine are never read
+            if !self.machines[i].inputs.is_empty() && !self.machines[i].halted {
+                active = true;
+            }
+            let mut polled = false;
+            loop {
+                match self.machines[i].run_until_event()? {
+                    Event::Output(value) => {
+                        self.partial[i].push(value);
+                        if self.partial[i].len() == 3 {
+                            let packet = Packet {
+                                dest: self.partial[i][0],
+                                x: self.partial[i][1],
+                                y: self.partial[i][2],
+                            };
+                            self.partial[i].clear();
+                            active = true;
+                            events.extend(self.route(packet));
+                        }
+                    }
+                    // Waiting for a packet: move on to the next machine
+                    Event::NeedsInput if polled => break,
+                    Event::NeedsInput => {
+                        self.machines[i].push_input(NO_PACKET);
+                        polled = true;
+                    }
+                    Event::Halted => break,
+                }
+            }
+        }
+
+        if active {
+            self.idle_rounds = 0;
+            return Ok(events);
+        }
+        self.idle_rounds += 1;
+        match (self.nat, self.nat_packet) {
+            (Some(nat), Some(packet)) => {
+                if self.idle_rounds >= nat.idle_rounds {
+                    let packet = Packet {
+                        dest: nat.target as i64,
+                        ..packet
+                    };
+                    self.idle_rounds = 0;
+                    events.push(NetEvent::NatSent(packet));
+                    events.extend(self.route(packet));
+                }
+            }
+            (Some(nat), None) if self.idle_rounds < nat.idle_rounds => (),
+            _ => events.push(NetEvent::Idle),
+        }
+        Ok(events)
+    }
+
+    /// Runs rounds until an event matches `stop`, or the network becomes
+    /// idle for good, and returns that event.
+    pub fn run_until<F: FnMut(&NetEvent) -> bool>(
+        &mut self,
+        mut stop: F,
+    ) -> Result<NetEvent, IntcodeError> {
+        loop {
+            for event in self.round()? {
+                if event == NetEvent::Idle || stop(&event) {
+                    return Ok(event);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asm::assemble;
+
+    /// Machine 0 sends (0, 1) to the NAT; then every machine sends each
+    /// packet it receives back to the NAT, unchanged.
+    const ECHO: &str = "
+        IN [address]
+        JNZ [address], #wait
+        OUT #255
+        OUT #0
+        OUT #1
+wait:   IN [x]
+        EQ [x], #-1, [empty]
+        JNZ [empty], #wait
+        IN [y]
+        OUT #255
+        OUT [x]
+        OUT [y]
+        JZ #0, #wait
+address: DATA 0
+x:      DATA 0
+y:      DATA 0
+empty:  DATA 0
+";
+
+    #[test]
+    fn nat() {
+        let program = assemble(ECHO).unwrap();
+        let mut network = Network::new(&program, 50, Some(Nat::default()));
+        let first = network.run_until(|event| matches!(event, NetEvent::NatReceived(_)));
+        assert_eq!(
+            first,
+            Ok(NetEvent::NatReceived(Packet {
+                dest: 255,
+                x: 0,
+                y: 1
+            }))
+        );
+
+        let mut last_y = None;
+        let repeated = network.run_until(|event| match event {
+            NetEvent::NatSent(packet) => {
+                let repeated = last_y == Some(packet.y);
+                last_y = Some(packet.y);
+                repeated
+            }
+            _ => false,
+        });
+        assert_eq!(
+            repeated,
+            Ok(NetEvent::NatSent(Packet {
+                dest: 0,
+                x: 0,
+                y: 1
+            }))
+        );
+    }
+
+    #[test]
+    fn without_nat() {
+        let program = assemble(ECHO).unwrap();
+        let mut network = Network::new(&program, 2, None);
+        assert_eq!(
+            network.round(),
+            Ok(vec![NetEvent::Unroutable(Packet {
+                dest: 255,
+                x: 0,
+                y: 1
+            })])
+        );
+        assert_eq!(network.run_until(|_| false), Ok(NetEvent::Idle));
+    }
+
+    #[test]
+    fn halted_machine() {
+        // Machine 0 sends a packet to machine 1, which halts without reading it
+        let program = assemble(
+            "
+        IN [address]
+        JNZ [address], #halt
+        OUT #1
+        OUT #5
+        OUT #6
+wait:   IN [address]
+        JZ #0, #wait
+halt:   HLT
+address: DATA 0
+",
+        )
+        .unwrap();
+        let mut network = Network::new(&program, 2, None);
+        assert_eq!(network.round(), Ok(vec![]));
+        assert_eq!(network.run_until(|_| false), Ok(NetEvent::Idle));
+        assert_eq!(network.machines[1].inputs.len(), 2);
+    }
+}
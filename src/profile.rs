@@ -0,0 +1,222 @@
+use super::computer::{Computer, OpCode};
+use super::disasm::decode;
+use super::word::Word;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Write;
+
+/// Execution counts collected while profiling a `Computer`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Profile {
+    /// Executions of the instruction at each address
+    pub executed: HashMap<usize, u64>,
+    /// Executions of each instruction, by mnemonic
+    pub opcodes: BTreeMap<&'static str, u64>,
+    /// Reads of each address by instruction parameters; fetching the
+    /// instructions themselves is not counted
+    pub reads: HashMap<usize, u64>,
+    pub writes: HashMap<usize, u64>,
+}
+
+fn percent(count: u64, total: u64) -> f64 {
+    100.0 * count as f64 / total.max(1) as f64
+}
+
+/// Entries sorted by decreasing count, then by address.
+fn sorted(counts: &HashMap<usize, u64>) -> Vec<(usize, u64)> {
+    let mut counts: Vec<_> = counts
+        .iter()
+        .map(|(&address, &count)| (address, count))
+        .collect();
+    counts.sort_by_key(|&(address, count)| (std::cmp::Reverse(count), address));
+    counts
+}
+
+impl Profile {
+    pub(crate) fn execute(&mut self, address: usize, op_code: OpCode) {
+        *self.executed.entry(address).or_insert(0) += 1;
+        *self.opcodes.entry(op_code.mnemonic()).or_insert(0) += 1;
+    }
+
+    /// Number of instructions executed.
+    pub fn total(&self) -> u64 {
+        self.executed.values().sum()
+    }
+
+    /// Executed addresses with their counts, most executed first.
+    pub fn hot_spots(&self) -> Vec<(usize, u64)> {
+        sorted(&self.executed)
+    }
+
+    /// Summary of the `top` most executed addresses, every opcode, and the
+    /// `top` most accessed memory addresses.
+    pub fn report(&self, top: usize) -> String {
+        let total = self.total();
+        let mut report = String::new();
+        writeln!(report, "{} instructions executed", total).unwrap();
+
+        writeln!(report, "\nHot spots:").unwrap();
+        writeln!(report, "{:>10} {:>12} {:>7}", "address", "count", "%").unwrap();
+        for (address, count) in self.hot_spots().into_iter().take(top) {
+            let share = percent(count, total);
+            writeln!(report, "{:>10} {:>12} {:>7.2}", address, count, share).unwrap();
+        }
+
+        writeln!(report, "\nOpcodes:").unwrap();
+        let mut opcodes: Vec<_> = self.opcodes.iter().collect();
+        opcodes.sort_by_key(|&(_, count)| std::cmp::Reverse(*count));
+        for (mnemonic, &count) in opcodes {
+            let share = percent(count, total);
+            writeln!(report, "{:>10} {:>12} {:>7.2}", mnemonic, count, share).unwrap();
+        }
+
+        writeln!(report, "\nMemory accesses:").unwrap();
+        writeln!(report, "{:>10} {:>12} {:>12}", "address", "reads", "writes").unwrap();
+        let mut accesses = self.reads.clone();
+        for (&address, &count) in &self.writes {
+            *accesses.entry(address).or_insert(0) += count;
+        }
+        for (address, _) in sorted(&accesses).into_iter().take(top) {
+            let reads = self.reads.get(&address).cloned().unwrap_or(0);
+            let writes = self.writes.get(&address).cloned().unwrap_or(0);
+            writeln!(report, "{:>10} {:>12} {:>12}", address, reads, writes).unwrap();
+        }
+        report
+    }
+
+    /// Disassembly of `memory`, as produced by `disasm::disassemble`, with
+    /// the execution count of every instruction and the access counts of
+    /// every data cell.
+    pub fn annotate(&self, memory: &[i64]) -> String {
+        let mut listing = String::new();
+        let mut address = 0;
+        while address < memory.len() {
+            match decode(memory, address) {
+                Some(instruction) => {
+                    let count = match self.executed.get(&address) {
+                        Some(count) => count.to_string(),
+                        None => "-".to_owned(),
+                    };
+                    writeln!(listing, "{:>12} {:>6}: {}", count, address, instruction).unwrap();
+                    address += instruction.size();
+                }
+                None => {
+                    write!(
+                        listing,
+                        "{:>12} {:>6}: DATA {}",
+                        "", address, memory[address]
+                    )
+                    .unwrap();
+                    let reads = self.reads.get(&address).cloned().unwrap_or(0);
+                    let writes = self.writes.get(&address).cloned().unwrap_or(0);
+                    if reads + writes > 0 {
+                        write!(listing, " ; {} reads, {} writes", reads, writes).unwrap();
+                    }
+                    writeln!(listing).unwrap();
+                    address += 1;
+                }
+            }
+        }
+        listing
+    }
+}
+
+impl<W: Word, I: Iterator<Item = W>> Computer<I, W> {
+    /// Starts counting executed instructions and memory accesses, from
+    /// scratch.
+    pub fn enable_profiling(&mut self) {
+        self.profile = Some(Profile::default());
+    }
+
+    pub fn disable_profiling(&mut self) {
+        self.profile = None;
+    }
+
+    /// Counts collected since profiling was enabled.
+    pub fn profile(&self) -> Option<&Profile> {
+        self.profile.as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use asm::assemble;
+    use compiler::Engine;
+    use computer::Computer;
+
+    const COUNTDOWN: &str = "
+loop: ADD [n], #-1, [n]
+      JNZ [n], #loop
+      OUT [n]
+      HLT
+n:    DATA 3
+";
+
+    #[test]
+    fn counts() {
+        let program = assemble(COUNTDOWN).unwrap();
+        let mut computer = Computer::new(program.clone(), None.into_iter());
+        // Profiling falls back to the interpreter
+        computer.set_engine(Engine::Compiled);
+        computer.enable_profiling();
+        assert_eq!(computer.run(), Ok(0));
+
+        let profile = computer.profile().unwrap();
+        assert_eq!(profile.total(), 8);
+        assert_eq!(profile.hot_spots(), vec![(0, 3), (4, 3), (7, 1), (9, 1)]);
+        assert_eq!(profile.opcodes["ADD"], 3);
+        assert_eq!(profile.opcodes["HLT"], 1);
+        assert_eq!(profile.reads[&10], 7);
+        assert_eq!(profile.writes[&10], 3);
+
+        let report = profile.report(2);
+        assert!(report.starts_with("8 instructions executed\n"));
+        assert!(report
+            .contains("\n         0            3   37.50\n         4            3   37.50\n\n"));
+        assert!(report.contains("\n       ADD            3   37.50\n"));
+        assert!(report.contains("\n        10            7            3\n"));
+
+        assert_eq!(
+            profile.annotate(&program),
+            "           3      0: ADD [10], #-1, [10]
+           3      4: JNZ [10], #0
+           1      7: OUT [10]
+           1      9: HLT
+                 10: DATA 3 ; 7 reads, 3 writes
+"
+        );
+    }
+
+    #[test]
+    fn unexecuted() {
+        let program = vec![3, 9, 1005, 9, 8, 104, 1, 99, 99, 0];
+        let mut computer = Computer::new(program.clone(), std::iter::once(1));
+        computer.enable_profiling();
+        computer.run().expect("Error while running program");
+        let listing = computer.profile().unwrap().annotate(&program);
+        assert!(listing.contains("\n           -      5: OUT #1\n"));
+        assert!(listing.contains("\n           1      8: HLT\n"));
+    }
+
+    #[test]
+    fn halted() {
+        let mut computer = Computer::new(vec![99], None.into_iter());
+        computer.enable_profiling();
+        for _ in 0..3 {
+            assert_eq!(computer.run(), Ok(0));
+        }
+        assert_eq!(computer.profile().unwrap().total(), 1);
+
+        // Halting again after executing other instructions counts again
+        computer.ip = 0;
+        computer.memory[0] = 1105;
+        computer.memory[1] = 1;
+        computer.memory[2] = 3;
+        computer.memory[3] = 99;
+        assert_eq!(computer.run(), Ok(0));
+        assert_eq!(computer.run(), Ok(0));
+        assert_eq!(
+            computer.profile().unwrap().hot_spots(),
+            vec![(0, 2), (3, 1)]
+        );
+    }
+}
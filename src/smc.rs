@@ -0,0 +1,162 @@
+use super::computer::Computer;
+use super::word::Word;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Modification {
+    /// The address was part of an executed instruction, then written
+    WrittenAfterExecution,
+    /// The address was written, then executed as part of an instruction
+    ExecutedAfterWrite,
+}
+
+/// A write of the program into its own code.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct SelfModification {
+    pub address: usize,
+    pub kind: Modification,
+    /// Address of the instruction that wrote the cell
+    pub writer: usize,
+}
+
+impl fmt::Display for SelfModification {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            Modification::WrittenAfterExecution => write!(
+                f,
+                "{}: written by the instruction at {} after being executed",
+                self.address, self.writer
+            ),
+            Modification::ExecutedAfterWrite => write!(
+                f,
+                "{}: executed after being written by the instruction at {}",
+                self.address, self.writer
+            ),
+        }
+    }
+}
+
+/// Addresses executed and written so far.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SmcDetector {
+    executed: HashSet<usize>,
+    /// Written addresses, with the last instruction that wrote them
+    written: HashMap<usize, usize>,
+    /// Instruction being executed
+    current: usize,
+    found: BTreeSet<SelfModification>,
+}
+
+impl SmcDetector {
+    /// Records the execution of the instruction taking `size` cells at
+    /// `address`.
+    pub(crate) fn execute(&mut self, address: usize, size: usize) {
+        self.current = address;
+        for cell in address..address + size {
+            if let Some(&writer) = self.written.get(&cell) {
+                self.found.insert(SelfModification {
+                    address: cell,
+                    kind: Modification::ExecutedAfterWrite,
+                    writer,
+                });
+            }
+            self.executed.insert(cell);
+        }
+    }
+
+    /// Records a write by the instruction being executed.
+    pub(crate) fn write(&mut self, address: usize) {
+        if self.executed.contains(&address) {
+            self.found.insert(SelfModification {
+                address,
+                kind: Modification::WrittenAfterExecution,
+                writer: self.current,
+            });
+        }
+        self.written.insert(address, self.current);
+    }
+}
+
+impl<W: Word, I: Iterator<Item = W>> Computer<I, W> {
+    /// Starts tracking the executed and written addresses, to find where the
+    /// program modifies its own code.
+    pub fn enable_smc_detection(&mut self) {
+        self.smc = Some(SmcDetector::default());
+    }
+
+    pub fn disable_smc_detection(&mut self) {
+        self.smc = None;
+    }
+
+    /// Self-modifications found since detection was enabled, sorted by
+    /// address. Each one is reported once per writing instruction.
+    pub fn self_modifications(&self) -> Vec<SelfModification> {
+        self.smc
+            .as_ref()
+            .map_or_else(Vec::new, |smc| smc.found.iter().cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use asm::assemble;
+    use decode_cache::tests::PATCH;
+
+    #[test]
+    fn patches() {
+        let program = assemble(PATCH).unwrap();
+        let mut computer = Computer::new(program, None.into_iter());
+        computer.enable_smc_detection();
+        assert_eq!(computer.run(), Ok(6));
+        let found = computer.self_modifications();
+        assert_eq!(
+            found,
+            vec![
+                SelfModification {
+                    address: 0,
+                    kind: Modification::WrittenAfterExecution,
+                    writer: 13,
+                },
+                SelfModification {
+                    address: 0,
+                    kind: Modification::ExecutedAfterWrite,
+                    writer: 13,
+                },
+            ]
+        );
+        assert_eq!(
+            found[0].to_string(),
+            "0: written by the instruction at 13 after being executed"
+        );
+    }
+
+    #[test]
+    fn written_before_execution() {
+        // Day 2 example: the first instruction turns the halt at 4 into a
+        // multiplication, which then overwrites the first instruction
+        let mut computer = Computer::new(vec![1, 1, 1, 4, 99, 5, 6, 0, 99], None.into_iter());
+        computer.enable_smc_detection();
+        computer.run().expect("Error while running program");
+        assert_eq!(
+            computer.self_modifications(),
+            vec![
+                SelfModification {
+                    address: 0,
+                    kind: Modification::WrittenAfterExecution,
+                    writer: 4,
+                },
+                SelfModification {
+                    address: 4,
+                    kind: Modification::ExecutedAfterWrite,
+                    writer: 0,
+                }
+            ]
+        );
+
+        let mut computer = Computer::new(vec![1, 1, 1, 4, 99, 5, 6, 0, 99], None.into_iter());
+        computer.run().expect("Error while running program");
+        assert!(computer.self_modifications().is_empty());
+    }
+}
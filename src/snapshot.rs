@@ -0,0 +1,248 @@
+//! Snapshots of a complete `Computer` state.
+//!
+//! Format, version 1: the magic bytes `ICSN`, the version byte, then
+//! LEB128 varints (zigzag-encoded when signed):
+//!
+//! - `ip`, relative base
+//! - number of memory segments, then for each segment its start address,
+//!   length and cells
+//! - number of pending inputs, then the inputs
+//! - number of outputs, then the outputs
+
+use super::computer::{Computer, InputQueue};
+use super::memory::Memory;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const MAGIC: &[u8] = b"ICSN";
+const VERSION: u8 = 1;
+
+#[derive(Debug)]
+pub enum SnapshotError {
+    Io(io::Error),
+    BadMagic,
+    UnsupportedVersion(u8),
+    Truncated,
+    /// A value does not fit in the machine words of this platform
+    Overflow,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SnapshotError::Io(e) => write!(f, "{}", e),
+            SnapshotError::BadMagic => write!(f, "not an Intcode snapshot"),
+            SnapshotError::UnsupportedVersion(version) => {
+                write!(f, "unsupported snapshot version {}", version)
+            }
+            SnapshotError::Truncated => write!(f, "truncated snapshot"),
+            SnapshotError::Overflow => write!(f, "snapshot value out of range"),
+        }
+    }
+}
+
+impl Error for SnapshotError {}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        SnapshotError::Io(e)
+    }
+}
+
+fn write_unsigned(out: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        out.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+fn write_signed(out: &mut Vec<u8>, value: i64) {
+    write_unsigned(out, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn unsigned(&mut self) -> Result<u64, SnapshotError> {
+        let mut value = 0u64;
+        let mut shift = 0;
+        loop {
+            let (&byte, rest) = self.data.split_first().ok_or(SnapshotError::Truncated)?;
+            self.data = rest;
+            if shift > 63 || (shift == 63 && byte > 1) {
+                return Err(SnapshotError::Overflow);
+            }
+            value |= u64::from(byte & 0x7f) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    fn usize(&mut self) -> Result<usize, SnapshotError> {
+        usize::try_from(self.unsigned()?).map_err(|_| SnapshotError::Overflow)
+    }
+
+    fn signed(&mut self) -> Result<i64, SnapshotError> {
+        let value = self.unsigned()?;
+        Ok((value >> 1) as i64 ^ -((value & 1) as i64))
+    }
+
+    fn signed_vec(&mut self) -> Result<Vec<i64>, SnapshotError> {
+        let len = self.usize()?;
+        // Do not trust the length for the allocation, a corrupted one would abort
+        let mut values = Vec::with_capacity(len.min(self.data.len()));
+        for _ in 0..len {
+            values.push(self.signed()?);
+        }
+        Ok(values)
+    }
+}
+
+impl Computer<InputQueue> {
+    /// Serializes the machine state: registers, memory and pending I/O.
+    /// Breakpoints and watchpoints are not part of the snapshot.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut out = MAGIC.to_vec();
+        out.push(VERSION);
+        write_unsigned(&mut out, self.ip as u64);
+        write_signed(&mut out, self.relative_base);
+        let segments = self.memory.segments();
+        write_unsigned(&mut out, segments.len() as u64);
+        for (start, cells) in segments {
+            write_unsigned(&mut out, start as u64);
+            write_unsigned(&mut out, cells.len() as u64);
+            for &cell in cells {
+                write_signed(&mut out, cell);
+            }
+        }
+        write_unsigned(&mut out, self.inputs.len() as u64);
+        for &input in self.inputs.iter() {
+            write_signed(&mut out, input);
+        }
+        write_unsigned(&mut out, self.outputs.len() as u64);
+        for &output in self.outputs.iter() {
+            write_signed(&mut out, output);
+        }
+        out
+    }
+
+    /// Rebuilds a machine from a snapshot made by `Computer::snapshot`.
+    pub fn restore(data: &[u8]) -> Result<Self, SnapshotError> {
+        if !data.starts_with(MAGIC) {
+            return Err(SnapshotError::BadMagic);
+        }
+        let version = *data.get(MAGIC.len()).ok_or(SnapshotError::Truncated)?;
+        if version != VERSION {
+            return Err(SnapshotError::UnsupportedVersion(version));
+        }
+        let mut reader = Reader {
+            data: &data[MAGIC.len() + 1..],
+        };
+        let ip = reader.usize()?;
+        let relative_base = reader.signed()?;
+        let mut memory = Memory::default();
+        for _ in 0..reader.usize()? {
+            let start = reader.usize()?;
+            let cells = reader.signed_vec()?;
+            start
+                .checked_add(cells.len())
+                .ok_or(SnapshotError::Overflow)?;
+            if start == 0 && memory.is_empty() {
+                memory = Memory::from(cells);
+            } else {
+                for (i, cell) in cells.into_iter().enumerate() {
+                    memory[start + i] = cell;
+                }
+            }
+        }
+        let inputs = reader.signed_vec()?;
+        let outputs = reader.signed_vec()?;
+
+        let mut computer = Computer::new(Vec::new(), inputs.into_iter().collect());
+        computer.ip = ip;
+        computer.relative_base = relative_base;
+        computer.memory = memory;
+        computer.outputs = outputs;
+        Ok(computer)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        fs::write(path, self.snapshot())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SnapshotError> {
+        Self::restore(&fs::read(path)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use computer::Event;
+
+    #[test]
+    fn roundtrip() {
+        // Reads two values, then outputs their sum after storing it far away
+        let program = vec![3, 100, 3, 101, 1, 100, 101, 5_000_000, 4, 5_000_000, 99];
+        let mut computer = Computer::new(program, InputQueue::new());
+        computer.relative_base = -42;
+        computer.outputs.push(7);
+        computer.push_input(1);
+        assert_eq!(computer.run_until_event(), Ok(Event::NeedsInput));
+        computer.push_input(2);
+        computer.push_input(-3);
+
+        let snapshot = computer.snapshot();
+        let mut restored = Computer::restore(&snapshot).unwrap();
+        assert_eq!(restored.snapshot(), snapshot);
+        assert_eq!(restored.ip, 2);
+        assert_eq!(restored.relative_base, -42);
+        assert_eq!(restored.outputs, vec![7]);
+        assert_eq!(restored.memory.to_vec(), computer.memory.to_vec());
+        assert_eq!(restored.inputs.iter().collect::<Vec<_>>(), vec![&2, &-3]);
+
+        assert_eq!(restored.run_until_event(), Ok(Event::Output(3)));
+        assert_eq!(restored.memory[5_000_000], 3);
+        let restored = Computer::restore(&restored.snapshot()).unwrap();
+        assert_eq!(restored.memory[5_000_000], 3);
+    }
+
+    #[test]
+    fn invalid() {
+        let computer = Computer::new(vec![1, 2, 3], InputQueue::new());
+        let snapshot = computer.snapshot();
+        match Computer::restore(&snapshot[..snapshot.len() - 1]) {
+            Err(SnapshotError::Truncated) => (),
+            _ => panic!("Truncated snapshot accepted"),
+        }
+        match Computer::restore(b"not a snapshot") {
+            Err(SnapshotError::BadMagic) => (),
+            _ => panic!("Invalid snapshot accepted"),
+        }
+        let mut snapshot = snapshot;
+        snapshot[MAGIC.len()] = 2;
+        match Computer::restore(&snapshot) {
+            Err(SnapshotError::UnsupportedVersion(2)) => (),
+            _ => panic!("Unknown version accepted"),
+        }
+
+        // A segment ending past the last address
+        let mut snapshot = MAGIC.to_vec();
+        snapshot.extend(&[VERSION, 0, 0, 1]);
+        write_unsigned(&mut snapshot, usize::MAX as u64);
+        snapshot.extend(&[2, 2, 4, 0, 0]);
+        match Computer::restore(&snapshot) {
+            Err(SnapshotError::Overflow) => (),
+            _ => panic!("Out of range segment accepted"),
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use num_bigint::BigInt;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::Rem;
+
+/// Value of an Intcode memory cell.
+///
+/// `i64` is the default. `i128` and `BigInt` let programs work with larger
+/// values; no type silently loses precision, unlike floating point values
+/// past 2^53.
+pub trait Word:
+    Clone
+    + fmt::Debug
+    + fmt::Display
+    + Default
+    + Ord
+    + Send
+    + Sync
+    + From<i64>
+    + Rem<Output = Self>
+    + 'static
+{
+    /// The value as an `i64`, or None if it does not fit.
+    fn to_i64(&self) -> Option<i64>;
+
+    fn checked_add(&self, other: &Self) -> Option<Self>;
+    fn checked_mul(&self, other: &Self) -> Option<Self>;
+    fn wrapping_add(&self, other: &Self) -> Self;
+    fn wrapping_mul(&self, other: &Self) -> Self;
+    fn saturating_add(&self, other: &Self) -> Self;
+    fn saturating_mul(&self, other: &Self) -> Self;
+}
+
+macro_rules! fixed_width_word {
+    ($type:ty) => {
+        impl Word for $type {
+            fn to_i64(&self) -> Option<i64> {
+                i64::try_from(*self).ok()
+            }
+
+            fn checked_add(&self, other: &Self) -> Option<Self> {
+                <$type>::checked_add(*self, *other)
+            }
+
+            fn checked_mul(&self, other: &Self) -> Option<Self> {
+                <$type>::checked_mul(*self, *other)
+            }
+
+            fn wrapping_add(&self, other: &Self) -> Self {
+                <$type>::wrapping_add(*self, *other)
+            }
+
+            fn wrapping_mul(&self, other: &Self) -> Self {
+                <$type>::wrapping_mul(*self, *other)
+            }
+
+            fn saturating_add(&self, other: &Self) -> Self {
+                <$type>::saturating_add(*self, *other)
+            }
+
+            fn saturating_mul(&self, other: &Self) -> Self {
+                <$type>::saturating_mul(*self, *other)
+            }
+        }
+    };
+}
+
+fixed_width_word!(i64);
+fixed_width_word!(i128);
+
+/// Never overflows: every policy gives the exact result.
+impl Word for BigInt {
+    fn to_i64(&self) -> Option<i64> {
+        i64::try_from(self).ok()
+    }
+
+    fn checked_add(&self, other: &Self) -> Option<Self> {
+        Some(self + other)
+    }
+
+    fn checked_mul(&self, other: &Self) -> Option<Self> {
+        Some(self * other)
+    }
+
+    fn wrapping_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn wrapping_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+
+    fn saturating_add(&self, other: &Self) -> Self {
+        self + other
+    }
+
+    fn saturating_mul(&self, other: &Self) -> Self {
+        self * other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use computer::Computer;
+
+    /// Squares its input over and over, outputting every square
+    const SQUARES: &[i64] = &[3, 11, 2, 11, 11, 11, 4, 11, 1105, 1, 2, 0];
+
+    fn squares<W: Word>(input: W, count: usize) -> Vec<W> {
+        let memory: Vec<W> = SQUARES.iter().map(|&value| W::from(value)).collect();
+        let computer = Computer::with_memory(memory, std::iter::once(input));
+        computer.take(count).map(Result::unwrap).collect()
+    }
+
+    #[test]
+    fn word_types() {
+        assert_eq!(squares(3i64, 4), vec![9, 81, 6561, 43046721]);
+        // Past 2^53, where a f64 could not tell it from its neighbours
+        assert_eq!(squares(94906267i64, 1), vec![9007199515875289]);
+
+        let last = squares(3i128, 6).pop().unwrap();
+        assert_eq!(last, 3i128.pow(64));
+        assert_eq!(last.to_i64(), None);
+
+        let last = squares(BigInt::from(3), 7).pop().unwrap();
+        assert_eq!(
+            last.to_string(),
+            "11790184577738583171520872861412518665678211592275841109096961"
+        );
+        assert_eq!(squares(BigInt::from(-2), 1)[0].to_i64(), Some(4));
+    }
+}